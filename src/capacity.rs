@@ -0,0 +1,187 @@
+use twilight::{
+    http::Client as HttpClient,
+    http::error::Error as DiscordError,
+    model::{
+        id::{ChannelId, GuildId},
+        user::User,
+    },
+};
+
+use crate::permissions::{has_permission, permission_denied};
+use crate::state::PersistentState;
+use crate::utils::{Result, parse_channel, send_alert, send_message};
+
+/// Discord's hard limit on the number of channels, categories included, in a guild
+pub const DISCORD_CHANNEL_LIMIT: u64 = 500;
+/// Every team gets a category, a text channel and a voice channel
+pub const CHANNELS_PER_TEAM: u64 = 3;
+/// Usage levels, in percent of the channel budget, at which organizers are warned
+const WARNING_THRESHOLDS: [u64; 3] = [75, 90, 95];
+
+/// A snapshot of how many channels the guild and its teams use
+pub struct ChannelUsage {
+    pub used: u64,
+    pub team_channels: u64,
+    pub budget: u64,
+}
+
+impl ChannelUsage {
+    /// Checks if another team's channels fit within the budget
+    pub fn has_room_for_team(&self) -> bool {
+        self.used + CHANNELS_PER_TEAM <= self.budget
+    }
+
+    /// The number of additional teams that fit within the budget
+    pub fn teams_left(&self) -> u64 {
+        self.budget.saturating_sub(self.used) / CHANNELS_PER_TEAM
+    }
+
+    fn percent(&self) -> u64 {
+        if self.budget == 0 { 100 } else { self.used * 100 / self.budget }
+    }
+
+    /// The highest warning threshold that the usage has reached
+    fn threshold(&self) -> u64 {
+        WARNING_THRESHOLDS.iter()
+            .rev()
+            .find(|threshold| self.percent() >= **threshold)
+            .cloned()
+            .unwrap_or(0)
+    }
+}
+
+/// Counts the channels currently in the guild and the ones used by teams
+pub async fn channel_usage(
+    http: &HttpClient,
+    guild: GuildId,
+) -> std::result::Result<ChannelUsage, DiscordError> {
+    let used = http.guild_channels(guild).await?.len() as u64;
+    let ps = PersistentState::instance().lock().unwrap();
+    Ok(ChannelUsage {
+        used,
+        team_channels: ps.channel_creators.len() as u64 * CHANNELS_PER_TEAM,
+        budget: ps.channel_budget,
+    })
+}
+
+/**
+  Warns organizers when the channel usage has crossed a new warning threshold
+  since the last warning. Should be called whenever channels are created or removed
+*/
+pub async fn check_usage_thresholds(
+    http: &HttpClient,
+    guild: GuildId,
+) -> Result<()> {
+    let usage = channel_usage(http, guild).await?;
+    let threshold = usage.threshold();
+    let warned = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let previous = ps.channel_usage_warned;
+        // Lower the warned level when usage drops so that the next rise is reported again
+        if threshold != previous {
+            ps.channel_usage_warned = threshold;
+            ps.save()?;
+        }
+        previous
+    };
+    if threshold > warned {
+        send_alert(http, format!(
+            "The server is using {} of its {} channel budget ({}%), {} of them for teams. \
+            There is room for {} more teams.",
+            usage.used, usage.budget, usage.percent(), usage.team_channels, usage.teams_left()
+        )).await?;
+    }
+    Ok(())
+}
+
+pub async fn handle_channel_usage(
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if has_permission(&http, guild, author.id, "channelusage").await? {
+        let usage = channel_usage(&http, guild).await?;
+        send_message(&http, original_channel, author.id,
+            format!(
+                "The server is using **{}** of its **{}** channel budget ({}%).\n\
+                Teams use **{}** channels and there is room for **{}** more teams.",
+                usage.used, usage.budget, usage.percent(), usage.team_channels, usage.teams_left()
+            )
+        ).await?;
+    }
+    else {
+        permission_denied(&http, original_channel, author.id, "channelusage", "view the channel usage").await?;
+    }
+    Ok(())
+}
+
+pub async fn handle_set_channel_budget<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if has_permission(&http, guild, author.id, "setchannelbudget").await? {
+        match rest_command.get(0).map(|arg| arg.parse::<u64>()) {
+            Some(Ok(budget)) if budget <= DISCORD_CHANNEL_LIMIT => {
+                {
+                    let mut ps = PersistentState::instance().lock().unwrap();
+                    ps.channel_budget = budget;
+                    ps.save()?;
+                }
+                send_message(&http, original_channel, author.id,
+                    format!("The channel budget is now **{}** channels.", budget)
+                ).await?;
+                if let Err(e) = check_usage_thresholds(&http, guild).await {
+                    println!("Failed to check channel usage: {:?}", e);
+                }
+            }
+            _ => {
+                send_message(&http, original_channel, author.id,
+                    format!(
+                        "Proper usage: `!setchannelbudget <number of channels>`, \
+                        where the budget is at most {}.", DISCORD_CHANNEL_LIMIT
+                    )
+                ).await?;
+            }
+        }
+    }
+    else {
+        permission_denied(&http, original_channel, author.id, "setchannelbudget", "set the channel budget").await?;
+    }
+    Ok(())
+}
+
+pub async fn handle_set_alert_channel<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if has_permission(&http, guild, author.id, "setalertchannel").await? {
+        let channel = match rest_command.get(0) {
+            Some(arg) => parse_channel(&http, guild, arg).await?,
+            None => None,
+        };
+        match channel {
+            Some(channel_id) => {
+                PersistentState::instance().lock().unwrap().set_alert_channel(channel_id)?;
+                send_message(&http, original_channel, author.id,
+                    format!("Organizer alerts will be posted in <#{}>.", channel_id)
+                ).await?;
+            }
+            None => {
+                send_message(&http, original_channel, author.id,
+                    "Proper usage: `!setalertchannel <channel>`"
+                ).await?;
+            }
+        }
+    }
+    else {
+        permission_denied(&http, original_channel, author.id, "setalertchannel", "set the alert channel").await?;
+    }
+    Ok(())
+}
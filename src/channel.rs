@@ -14,28 +14,55 @@ use twilight::{
             permission_overwrite::{PermissionOverwrite, PermissionOverwriteType},
         },
         guild::Permissions,
-        id::{ChannelId, GuildId, UserId},
+        id::{ChannelId, GuildId, MessageId, UserId},
     },
 };
 
-use crate::role::has_role;
-use crate::roles::ORGANIZER;
+use crate::capacity::{channel_usage, check_usage_thresholds};
+use crate::devlog::DevlogEntry;
+use crate::naming::{derive_names, NameError};
+use crate::permissions::{has_permission, permission_denied};
 use crate::state::PersistentState;
-use crate::utils::{Result, send_message};
+use crate::submission::Submission;
+use crate::team_order::sort_team_categories;
+use crate::utils::{Result, format_duration, parse_user, send_message, unix_now};
+use crate::voice::set_voice_region;
+
+/// Number of renames allowed within `RENAME_WINDOW_SECS`, mirroring Discord's
+/// rate limit of two channel renames per ten minutes
+const RENAME_LIMIT: usize = 2;
+const RENAME_WINDOW_SECS: u64 = 10 * 60;
 
 lazy_static! {
-    static ref INVALID_REGEX: Regex = Regex::new("[`|]+").unwrap();
     static ref MARKDOWN_ESCAPE_REGEX: Regex = Regex::new("[-_+*\"#=.⋅\\\\<>{}]+").unwrap();
 }
 
-fn permissions_deny_send(user_id: UserId) -> Vec<PermissionOverwrite> {
+/**
+  Overwrites for the bot in a team's text channel. The bot used to be denied
+  sending messages there to keep team channels free of its chatter, but it
+  now posts the devlog check-ins in them, so sending is allowed instead
+*/
+fn bot_text_overwrites(user_id: UserId) -> Vec<PermissionOverwrite> {
     vec!(PermissionOverwrite {
-        allow: Permissions::empty(),
-        deny: Permissions::SEND_MESSAGES,
+        allow: Permissions::SEND_MESSAGES,
+        deny: Permissions::empty(),
         kind: PermissionOverwriteType::Member(user_id),
     })
 }
 
+/// Lets the user see and use the team's channels, regardless of the channels' default permissions
+pub async fn grant_team_access(http: &HttpClient, team: &Team, user_id: UserId) -> Result<()> {
+    let text = Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES | Permissions::ATTACH_FILES;
+    let voice = Permissions::VIEW_CHANNEL | Permissions::CONNECT | Permissions::SPEAK;
+    http.update_channel_permission(team.category_id, text | voice, Permissions::empty())
+        .member(user_id).await?;
+    http.update_channel_permission(team.text_id, text, Permissions::empty())
+        .member(user_id).await?;
+    http.update_channel_permission(team.voice_id, voice, Permissions::empty())
+        .member(user_id).await?;
+    Ok(())
+}
+
 fn to_markdown_safe<'a>(name: &'a str) -> String {
     MARKDOWN_ESCAPE_REGEX.replace_all(name,
         |caps: &Captures| {
@@ -46,10 +73,68 @@ fn to_markdown_safe<'a>(name: &'a str) -> String {
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Team {
-    game_name: String,
-    category_id: ChannelId,
-    text_id: ChannelId,
-    voice_id: ChannelId,
+    pub game_name: String,
+    pub category_id: ChannelId,
+    pub text_id: ChannelId,
+    pub voice_id: ChannelId,
+    /// Unix timestamps of recent renames, used to enforce the rename cooldown
+    #[serde(default)]
+    rename_times: Vec<u64>,
+    /// Members of the team other than the user who created it
+    #[serde(default)]
+    pub members: Vec<UserId>,
+    #[serde(default)]
+    pub submission: Option<Submission>,
+    /// Skill roles the team is looking for
+    #[serde(default)]
+    pub needed_roles: Vec<String>,
+    /// The latest check-in prompt posted in the text channel
+    #[serde(default)]
+    pub last_prompt_id: Option<MessageId>,
+    /// Replies to check-in prompts
+    #[serde(default)]
+    pub devlog: Vec<DevlogEntry>,
+}
+
+impl Team {
+    /// Drops renames older than the rename window and returns the number of
+    /// seconds until another rename is allowed, if the team is on cooldown
+    fn rename_cooldown(&mut self, now: u64) -> Option<u64> {
+        self.rename_times.retain(|time| now < time + RENAME_WINDOW_SECS);
+        if self.rename_times.len() >= RENAME_LIMIT {
+            self.rename_times.iter()
+                .min()
+                .map(|oldest| oldest + RENAME_WINDOW_SECS - now)
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl PersistentState {
+    /**
+      Checks if any team, except the one created by `ignored_user`, already
+      uses the game name. The comparison is case insensitive
+    */
+    fn is_game_name_taken(&self, game_name: &str, ignored_user: Option<UserId>) -> bool {
+        let game_name = to_markdown_safe(game_name).to_lowercase();
+        self.channel_creators.iter()
+            .filter(|(creator, _)| Some(**creator) != ignored_user)
+            .any(|(_, team)| team.game_name.to_lowercase() == game_name)
+    }
+
+    /// Gets the creator of the team the user is part of, as its creator or as a member
+    pub fn team_of(&self, user: UserId) -> Option<UserId> {
+        if self.channel_creators.contains_key(&user) {
+            Some(user)
+        }
+        else {
+            self.channel_creators.iter()
+                .find(|(_, team)| team.members.contains(&user))
+                .map(|(creator, _)| *creator)
+        }
+    }
 }
 
 pub async fn handle_create_channels<'a>(
@@ -62,7 +147,8 @@ pub async fn handle_create_channels<'a>(
 ) -> Result<()> {
 
     let result = create_team(
-        rest_command,
+        &rest_command.join(" "),
+        Vec::new(),
         guild_id,
         user_id,
         current_user_id,
@@ -77,6 +163,12 @@ pub async fn handle_create_channels<'a>(
                     team.game_name, team.text_id
                 )
             ).await?;
+            if let Err(e) = sort_team_categories(&http, guild_id).await {
+                println!("Failed to sort team categories: {:?}", e);
+            }
+            if let Err(e) = check_usage_thresholds(&http, guild_id).await {
+                println!("Failed to check channel usage: {:?}", e);
+            }
         }
         Err(ref e) => {
             send_message(&http, original_channel_id, user_id,
@@ -91,20 +183,19 @@ pub async fn handle_create_channels<'a>(
 pub async fn handle_rename_channels<'a>(
     rest_command: &[&'a str],
     original_channel_id: ChannelId,
+    guild_id: GuildId,
     user_id: UserId,
-    current_user_id: UserId,
     http: HttpClient
 ) -> Result<()> {
 
     if rest_command.len() > 0 {
-        let new_name = &*rest_command.join(" ");
-
-        if INVALID_REGEX.is_match(&new_name) {
-            send_message(&http, original_channel_id, user_id,
-                "Game names cannot contain the characters ` or |"
-            ).await?;
-            return Ok(());
-        }
+        let names = match derive_names(&rest_command.join(" ")) {
+            Ok(names) => names,
+            Err(e) => {
+                send_message(&http, original_channel_id, user_id, format!("{}", e)).await?;
+                return Ok(());
+            }
+        };
 
         if !PersistentState::instance().lock().unwrap().has_created_channel(user_id) {
             send_message(&http, original_channel_id, user_id,
@@ -114,16 +205,31 @@ pub async fn handle_rename_channels<'a>(
                 )
             ).await?;
         }
+        else if PersistentState::instance().lock().unwrap().is_game_name_taken(&names.display, Some(user_id)) {
+            send_message(&http, original_channel_id, user_id,
+                format!("Another team is already using the game name **{}**.", to_markdown_safe(&names.display))
+            ).await?;
+        }
         else {
             let mut team = PersistentState::instance().lock().unwrap().get_channel_info(user_id).cloned().unwrap();
-            team.game_name = to_markdown_safe(new_name);
-            PersistentState::instance().lock().unwrap().register_channel_creation(user_id, &team)?;
+            let now = unix_now();
+            if let Some(wait) = team.rename_cooldown(now) {
+                send_message(&http, original_channel_id, user_id,
+                    format!(
+                        "Discord only allows renaming channels {} times every {}. \
+                        You can rename the channels for **{}** again in {}.",
+                        RENAME_LIMIT, format_duration(RENAME_WINDOW_SECS), team.game_name, format_duration(wait)
+                    )
+                ).await?;
+                return Ok(());
+            }
+            team.game_name = to_markdown_safe(&names.display);
 
             let mut oks = Vec::new();
             let mut errs = Vec::new();
             match http.update_channel(team.category_id)
             .kind(ChannelType::GuildCategory)
-            .name(new_name)
+            .name(&names.category)
             .await {
                 Ok(Channel::Guild(GuildChannel::Category(category))) => {
                     oks.push(format!("category to **{}**", category.name));
@@ -136,8 +242,9 @@ pub async fn handle_rename_channels<'a>(
             .parent_id(team.category_id)
             .kind(ChannelType::GuildText)
             .topic(format!("Work on and playtesting of the game {}.", team.game_name))
-            .permission_overwrites(permissions_deny_send(current_user_id))
-            .name(new_name).await {
+            // The permission overwrites are left alone, since they hold the access
+            // of team members and the bot
+            .name(&names.text).await {
                 Ok(Channel::Guild(GuildChannel::Category(text))) => {
                     oks.push(format!("text channel to **#{}** (found here: <#{}>)", text.name, text.id));
                 }
@@ -148,7 +255,7 @@ pub async fn handle_rename_channels<'a>(
             match http.update_channel(team.voice_id)
             .parent_id(team.category_id)
             .kind(ChannelType::GuildVoice)
-            .name(new_name).await {
+            .name(&names.voice).await {
                 Ok(Channel::Guild(GuildChannel::Category(voice))) => {
                     oks.push(format!("voice channel to **{}**", voice.name));
                 }
@@ -157,6 +264,17 @@ pub async fn handle_rename_channels<'a>(
                 }
             }
 
+            // Only renames that went through count towards the cooldown. The team is
+            // read again since other fields may have changed during the requests
+            if oks.len() > 0 {
+                let mut ps = PersistentState::instance().lock().unwrap();
+                if let Some(stored) = ps.channel_creators.get_mut(&user_id) {
+                    stored.game_name = team.game_name.clone();
+                    stored.rename_times.push(now);
+                    ps.save()?;
+                }
+            }
+
             let message =
             if oks.len() > 0 {
                 if errs.len() > 0 {
@@ -178,6 +296,10 @@ pub async fn handle_rename_channels<'a>(
             };
 
             send_message(&http, original_channel_id, user_id, message).await?;
+
+            if let Err(e) = sort_team_categories(&http, guild_id).await {
+                println!("Failed to sort team categories: {:?}", e);
+            }
         }
     }
     Ok(())
@@ -190,51 +312,21 @@ pub async fn handle_remove_channels<'a>(
     author_id: UserId,
     http: HttpClient
 ) -> Result<()> {
-    // Only let organizers use this command
-    if !has_role(&http, guild_id, author_id, ORGANIZER).await? {
-        send_message(&http, original_channel_id, author_id,
-            format!("You need to be an **organizer** to use this command.")
-        ).await?
+    if !has_permission(&http, guild_id, author_id, "removechannels").await? {
+        permission_denied(&http, original_channel_id, author_id, "removechannels", "remove channels").await?
     }
     else {
         if rest_command.len() > 0 {
-
-            lazy_static! {
-                static ref USER_MENTION_REGEX: Regex =
-                    Regex::new(r"<@!(\d+)>").unwrap();
-            }
-            let id_str: String = match USER_MENTION_REGEX.captures(rest_command[0]) {
-                Some(user_ids) => {
-                    if user_ids.len() == 2 {
-                        user_ids[1].to_string()
-                    }
-                    else {
-                        send_message(&http, original_channel_id, author_id,
-                            "Invalid user reference."
-                        ).await?;
-                        return Ok(())
-                    }
-                }
-                _ => {
+            let user_id = match parse_user(&http, guild_id, &rest_command.join(" ")).await? {
+                Some(user_id) => user_id,
+                None => {
                     send_message(&http, original_channel_id, author_id,
-                        "Invalid user reference."
+                        "Invalid user reference. Use a mention, a user ID, a name or `name#1234`."
                     ).await?;
                     return Ok(())
                 }
             };
 
-            let id = match id_str.parse::<u64>() {
-                Ok(id) => id,
-                Err(_) => {
-                    send_message(&http, original_channel_id, author_id,
-                        format!("That user id is invalid.")
-                    ).await?;
-                    return Ok(())
-                },
-            };
-
-            let user_id = UserId(id);
-
             if !PersistentState::instance().lock().unwrap().has_created_channel(user_id) {
                 send_message(&http, original_channel_id, author_id,
                     format!("That user does not have any team channels.")
@@ -273,6 +365,9 @@ pub async fn handle_remove_channels<'a>(
                 }
 
                 PersistentState::instance().lock().unwrap().remove_channel(user_id).unwrap();
+                if let Err(e) = check_usage_thresholds(&http, guild_id).await {
+                    println!("Failed to check channel usage: {:?}", e);
+                }
 
                 let message =
                 if oks.len() > 0 {
@@ -325,8 +420,13 @@ fn list_strings(
     result
 }
 
-async fn create_team<'a>(
-    rest_command: &[&'a str],
+/**
+  Creates the category, text channel and voice channel for a game and
+  registers `user` as the creator of the team, with `members` as its other members
+*/
+pub async fn create_team(
+    game_name: &str,
+    members: Vec<UserId>,
     guild: GuildId,
     user: UserId,
     current_user_id: UserId,
@@ -337,18 +437,24 @@ async fn create_team<'a>(
         Err(ChannelCreationError::AlreadyCreated(user))
     }
     else {
-        let game_name = &*rest_command.join(" ");
         println!("Got a request for channels for the game {:?}", game_name);
-        if rest_command.len() == 0 {
+        if game_name.trim().is_empty() {
             Err(ChannelCreationError::NoName)
         }
-        else if INVALID_REGEX.is_match(game_name) {
-            Err(ChannelCreationError::InvalidName)
-        }
         else {
-            let category_name = format!("Team: {}", game_name);
+            let names = derive_names(game_name).map_err(ChannelCreationError::InvalidName)?;
+            if PersistentState::instance().lock().unwrap().is_game_name_taken(&names.display, None) {
+                return Err(ChannelCreationError::NameTaken(to_markdown_safe(&names.display)));
+            }
+
+            let usage = channel_usage(http, guild).await
+                .map_err(ChannelCreationError::UsageCheckFailed)?;
+            if !usage.has_room_for_team() {
+                return Err(ChannelCreationError::BudgetExceeded(usage.budget));
+            }
+
             // Create a category
-            let category = http.create_guild_channel(guild, category_name)
+            let category = http.create_guild_channel(guild, &names.category)
                 .kind(ChannelType::GuildCategory)
                 .await
                 .map_err(ChannelCreationError::CategoryCreationFailed)
@@ -361,11 +467,11 @@ async fn create_team<'a>(
                     }
                 })?;
 
-            let text = http.create_guild_channel(guild, game_name)
+            let text = http.create_guild_channel(guild, &names.text)
                 .parent_id(category.id)
                 .kind(ChannelType::GuildText)
-                .topic(format!("Work on and playtesting of the game {}.", game_name))
-                .permission_overwrites(permissions_deny_send(current_user_id))
+                .topic(format!("Work on and playtesting of the game {}.", names.display))
+                .permission_overwrites(bot_text_overwrites(current_user_id))
                 .await
                 .map_err(|e| ChannelCreationError::TextCreationFailed(e))
                 .and_then(|maybe_text| {
@@ -377,9 +483,17 @@ async fn create_team<'a>(
                     }
                 })?;
 
-            let voice = http.create_guild_channel(guild, game_name)
+            let voice_config = PersistentState::instance().lock().unwrap().voice_config.clone();
+            let mut voice_request = http.create_guild_channel(guild, &names.voice)
                 .parent_id(category.id)
-                .kind(ChannelType::GuildVoice)
+                .kind(ChannelType::GuildVoice);
+            if let Some(user_limit) = voice_config.user_limit {
+                voice_request = voice_request.user_limit(user_limit);
+            }
+            if let Some(bitrate) = voice_config.bitrate {
+                voice_request = voice_request.bitrate(bitrate);
+            }
+            let voice = voice_request
                 .await
                 .map_err(|e| ChannelCreationError::VoiceCreationFailed(e))
                 .and_then(|maybe_voice| {
@@ -390,12 +504,23 @@ async fn create_team<'a>(
                         _ => Err(ChannelCreationError::VoiceNotCreated)
                     }
                 })?;
+            if let Some(region) = &voice_config.region {
+                if let Err(e) = set_voice_region(http, voice.id, Some(region)).await {
+                    println!("Failed to set voice region {} for {}: {:?}", region, names.display, e);
+                }
+            }
 
             let team = Team {
-                game_name: to_markdown_safe(game_name),
+                game_name: to_markdown_safe(&names.display),
                 category_id: category.id,
                 text_id: text.id,
-                voice_id: voice.id
+                voice_id: voice.id,
+                rename_times: Vec::new(),
+                members,
+                submission: None,
+                needed_roles: Vec::new(),
+                last_prompt_id: None,
+                devlog: Vec::new(),
             };
             PersistentState::instance().lock().unwrap()
                 .register_channel_creation(user, &team)
@@ -412,13 +537,17 @@ async fn create_team<'a>(
   The Display implementation is intended to be sent back to the user
 */
 #[derive(Debug)]
-enum ChannelCreationError {
+pub enum ChannelCreationError {
     /// The user has already created a channel
     AlreadyCreated(UserId),
     /// No name was specified
     NoName,
-    /// The user used invalid characters in the channel name
-    InvalidName,
+    /// No valid channel names could be derived from the game name
+    InvalidName(NameError),
+    /// Another team already uses the game name
+    NameTaken(String),
+    /// Creating the channels would exceed the channel budget
+    BudgetExceeded(u64),
     /// The discord API said everything was fine but created something
     /// that was not a category
     CategoryNotCreated,
@@ -428,6 +557,8 @@ enum ChannelCreationError {
     /// The discord API said everything was fine but created something
     /// that was not a voice channel
     VoiceNotCreated,
+    /// The discord API returned an error when counting the guild's channels
+    UsageCheckFailed(DiscordError),
     /// The discord API returned an error when creating category
     CategoryCreationFailed(DiscordError),
     /// The discord API returned an error when creating text channel
@@ -453,8 +584,13 @@ impl Display for ChannelCreationError {
                 "I asked Discord for a text channel but got something else. 🤔".to_string(),
             Self::VoiceNotCreated =>
                 "I asked Discord for a voice channel but got something else. 🤔".to_string(),
-            Self::InvalidName =>
-                "Game names cannot contain the characters ` or |".to_string(),
+            Self::InvalidName(e) => format!("{}", e),
+            Self::NameTaken(game_name) =>
+                format!("Another team is already using the game name **{}**.", game_name),
+            Self::BudgetExceeded(budget) =>
+                format!("The server has reached its budget of {} channels, so no more \
+                    teams can be created. Please contact an organizer.", budget),
+            Self::UsageCheckFailed(_) => "Could not check how many channels the server uses.".to_string(),
             Self::CategoryCreationFailed(_) => "Category creation failed.".to_string(),
             Self::TextCreationFailed(_) => "Text channel creation failed.".to_string(),
             Self::VoiceCreationFailed(_) => "Voice channel creation failed.".to_string(),
@@ -471,8 +607,11 @@ impl std::error::Error for ChannelCreationError {
                 | Self::CategoryNotCreated
                 | Self::TextNotCreated
                 | Self::VoiceNotCreated
-                | Self::InvalidName => None,
-            Self::CategoryCreationFailed(e)
+                | Self::NameTaken(_)
+                | Self::BudgetExceeded(_) => None,
+            Self::InvalidName(e) => Some(e),
+            Self::UsageCheckFailed(e)
+                | Self::CategoryCreationFailed(e)
                 | Self::TextCreationFailed(e)
                 | Self::VoiceCreationFailed(e) => Some(e)
         }
@@ -0,0 +1,216 @@
+use std::time::Duration;
+
+use serde_derive::{Serialize, Deserialize};
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId},
+        user::User,
+    },
+};
+
+use crate::permissions::{has_permission, permission_denied};
+use crate::state::PersistentState;
+use crate::utils::{
+    Result, format_duration, format_timestamp, parse_duration, send_long_message, send_message,
+    unix_now,
+};
+
+/// How often the scheduler checks if a check-in is due
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_PROMPT: &str = "How is your game coming along? Reply to this message with \
+    a short progress update, it will end up in your team's devlog!";
+
+/// Organizer configured check-ins posted in every team's text channel
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CheckinSchedule {
+    pub interval_secs: u64,
+    pub prompt: String,
+    /// Unix timestamp of the next check-in
+    pub next_at: u64,
+}
+
+/// A reply to a check-in prompt
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DevlogEntry {
+    pub author: String,
+    pub content: String,
+    pub posted_at: u64,
+}
+
+/**
+  Posts the check-in prompt in every team's text channel whenever a
+  check-in is due. Runs for as long as the bot does
+*/
+pub async fn run_checkin_scheduler(http: HttpClient) {
+    loop {
+        tokio::time::delay_for(SCHEDULER_INTERVAL).await;
+        if let Err(e) = post_due_checkins(&http).await {
+            println!("Failed to post check-ins: {:?}", e);
+        }
+    }
+}
+
+async fn post_due_checkins(http: &HttpClient) -> Result<()> {
+    let now = unix_now();
+    let due = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let prompt = match ps.checkin_schedule.as_mut() {
+            Some(schedule) if schedule.next_at <= now => {
+                // Skip check-ins missed while the bot was offline instead of posting them all
+                while schedule.next_at <= now {
+                    schedule.next_at += schedule.interval_secs;
+                }
+                Some(schedule.prompt.clone())
+            }
+            _ => None,
+        };
+        match prompt {
+            Some(prompt) => {
+                let channels = ps.channel_creators.iter()
+                    .map(|(creator, team)| (*creator, team.text_id))
+                    .collect::<Vec<_>>();
+                ps.save()?;
+                Some((prompt, channels))
+            }
+            None => None,
+        }
+    };
+
+    if let Some((prompt, channels)) = due {
+        println!("Posting check-in in {} team channels", channels.len());
+        for (creator, channel_id) in channels {
+            match http.create_message(channel_id).content(prompt.clone()).await {
+                Ok(prompt_msg) => {
+                    let mut ps = PersistentState::instance().lock().unwrap();
+                    if let Some(team) = ps.channel_creators.get_mut(&creator) {
+                        team.last_prompt_id = Some(prompt_msg.id);
+                    }
+                    ps.save()?;
+                }
+                // Channels created before check-ins existed still deny the bot sending messages
+                Err(e) => println!("Failed to post check-in in {}: {:?}", channel_id, e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+  Adds the message to the team's devlog if it is a reply to the latest
+  check-in prompt in the team's text channel. Other messages posted after
+  the prompt are regular chat and are not collected
+*/
+pub fn handle_potential_devlog_entry(msg: &Message) -> Result<()> {
+    let replied_to = match msg.reference.as_ref().and_then(|reference| reference.message_id) {
+        Some(replied_to) => replied_to,
+        None => return Ok(()),
+    };
+    let mut ps = PersistentState::instance().lock().unwrap();
+    let team = ps.channel_creators.values_mut()
+        .find(|team| team.text_id == msg.channel_id && team.last_prompt_id == Some(replied_to));
+    if let Some(team) = team {
+        team.devlog.push(DevlogEntry {
+            author: msg.author.name.clone(),
+            content: msg.content.clone(),
+            posted_at: unix_now(),
+        });
+        ps.save()?;
+    }
+    Ok(())
+}
+
+pub async fn handle_set_checkins<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "setcheckins").await? {
+        permission_denied(&http, original_channel, author.id, "setcheckins", "schedule check-ins").await?;
+        return Ok(());
+    }
+
+    let schedule = match rest_command.get(0) {
+        Some(&"off") => Some(None),
+        Some(interval) => parse_duration(interval)
+            .filter(|interval| *interval >= 60)
+            .map(|interval_secs| {
+                let prompt = if rest_command.len() > 1 {
+                    rest_command[1..].join(" ")
+                }
+                else {
+                    DEFAULT_PROMPT.to_string()
+                };
+                Some(CheckinSchedule { interval_secs, prompt, next_at: unix_now() + interval_secs })
+            }),
+        None => None,
+    };
+
+    match schedule {
+        Some(schedule) => {
+            let reply = match &schedule {
+                Some(schedule) => format!(
+                    "Teams will be asked for a progress update every {}, starting {}.",
+                    format_duration(schedule.interval_secs), format_timestamp(schedule.next_at)
+                ),
+                None => "Check-ins have been turned off.".to_string(),
+            };
+            {
+                let mut ps = PersistentState::instance().lock().unwrap();
+                ps.checkin_schedule = schedule;
+                ps.save()?;
+            }
+            send_message(&http, original_channel, author.id, reply).await?;
+        }
+        None => {
+            send_message(&http, original_channel, author.id,
+                "Proper usage: `!setcheckins <interval> [prompt]` or `!setcheckins off`, \
+                for example `!setcheckins 6h What did you work on?`"
+            ).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_export_devlogs(
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "exportdevlogs").await? {
+        permission_denied(&http, original_channel, author.id, "exportdevlogs", "export devlogs").await?;
+        return Ok(());
+    }
+
+    let mut teams = {
+        let ps = PersistentState::instance().lock().unwrap();
+        ps.channel_creators.values()
+            .filter(|team| !team.devlog.is_empty())
+            .map(|team| (team.game_name.clone(), team.devlog.clone()))
+            .collect::<Vec<_>>()
+    };
+    teams.sort_by_key(|(game_name, _)| game_name.to_lowercase());
+
+    let message = if teams.is_empty() {
+        "No devlog entries have been collected yet.".to_string()
+    }
+    else {
+        let devlogs = teams.iter()
+            .map(|(game_name, devlog)| {
+                let entries = devlog.iter()
+                    .map(|entry| format!("{} **{}**: {}", format_timestamp(entry.posted_at), entry.author, entry.content))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("__**{}**__\n{}", game_name, entries)
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        format!("Devlogs of {} teams:\n\n{}", teams.len(), devlogs)
+    };
+    send_long_message(&http, original_channel, author.id, message).await?;
+    Ok(())
+}
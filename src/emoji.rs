@@ -0,0 +1,211 @@
+use std::fmt::{self, Display};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_derive::{Serialize, Deserialize};
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        channel::ReactionType,
+        guild::Emoji,
+        id::{EmojiId, GuildId},
+    },
+};
+
+lazy_static! {
+    static ref CUSTOM_EMOJI_REGEX: Regex = Regex::new(r"^<(a?):(\w+):(\d+)>$").unwrap();
+}
+
+/**
+  An emoji that gives a role when reacted with. Either a unicode emoji or
+  a custom emoji of the server, which is identified by its ID.
+
+  Unicode emoji are stored as plain strings, like they were before custom
+  emoji were supported
+*/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum RoleEmoji {
+    Custom {
+        id: EmojiId,
+        name: String,
+        animated: bool,
+    },
+    Unicode(String),
+}
+
+impl RoleEmoji {
+    /// Parses a command argument, where custom emoji look like `<:name:id>`
+    pub fn parse(arg: &str) -> Option<Self> {
+        if let Some(captures) = CUSTOM_EMOJI_REGEX.captures(arg) {
+            return captures[3].parse::<u64>().ok().map(|id| Self::Custom {
+                id: EmojiId(id),
+                name: captures[2].to_string(),
+                animated: !captures[1].is_empty(),
+            });
+        }
+        // Anything else must be made of emoji characters. Digits, `#` and `*` only
+        // occur in the keycap emoji, like 1️⃣, where they are followed by a
+        // variation selector or the keycap itself
+        let chars = arg.chars().collect::<Vec<_>>();
+        let mut has_emoji = false;
+        for (i, c) in chars.iter().enumerate() {
+            if is_pictographic(*c) {
+                has_emoji = true;
+            }
+            else if is_keycap_base(*c) && matches!(chars.get(i + 1), Some('\u{FE0F}') | Some('\u{20E3}')) {
+                has_emoji = true;
+            }
+            else if !is_emoji_component(*c) {
+                return None;
+            }
+        }
+        if has_emoji {
+            Some(Self::Unicode(arg.to_string()))
+        }
+        else {
+            None
+        }
+    }
+
+    /// Checks if a reaction was made with this emoji
+    pub fn matches(&self, reaction: &ReactionType) -> bool {
+        match (self, reaction) {
+            (Self::Custom { id, .. }, ReactionType::Custom { id: reacted, .. }) => id == reacted,
+            (Self::Unicode(name), ReactionType::Unicode { name: reacted }) => name == reacted,
+            _ => false,
+        }
+    }
+
+    /// Formats the emoji the way the reaction endpoints expect it
+    pub fn reaction_string(&self) -> String {
+        match self {
+            Self::Custom { id, name, .. } => format!("{}:{}", name, id),
+            Self::Unicode(name) => name.clone(),
+        }
+    }
+
+    /**
+      Checks that the bot can react with the emoji. Custom emoji must be one
+      of `guild_emojis` and be available, unicode emoji always work
+    */
+    pub fn is_usable(&self, guild_emojis: &[Emoji]) -> bool {
+        match self {
+            Self::Custom { id, .. } => guild_emojis.iter().any(|emoji| emoji.id == *id && emoji.available),
+            Self::Unicode(_) => true,
+        }
+    }
+}
+
+/**
+  Checks if the character is a pictograph or regional indicator, going by
+  the ranges of the Extended_Pictographic property of Unicode. Whole blocks
+  are allowed where most of the block is pictographic
+*/
+fn is_pictographic(c: char) -> bool {
+    matches!(c as u32,
+        0xA9 | 0xAE | 0x203C | 0x2049 | 0x2122 | 0x2139
+        | 0x2194..=0x2199 | 0x21A9..=0x21AA | 0x231A..=0x231B | 0x2328 | 0x23CF
+        | 0x23E9..=0x23F3 | 0x23F8..=0x23FA | 0x24C2 | 0x25AA..=0x25AB | 0x25B6 | 0x25C0
+        | 0x25FB..=0x25FE | 0x2600..=0x27BF | 0x2934..=0x2935 | 0x2B05..=0x2B07
+        | 0x2B1B..=0x2B1C | 0x2B50 | 0x2B55 | 0x3030 | 0x303D | 0x3297 | 0x3299
+        // Mahjong tiles up to the newest emoji blocks, including regional indicators
+        | 0x1F000..=0x1FAFF | 0x1FC00..=0x1FFFD
+    )
+}
+
+/// Characters that start a keycap emoji
+fn is_keycap_base(c: char) -> bool {
+    c.is_ascii_digit() || c == '#' || c == '*'
+}
+
+/**
+  Characters that only modify the emoji around them: joiners, variation
+  selectors, the keycap, skin tones and the tags of subdivision flags
+*/
+fn is_emoji_component(c: char) -> bool {
+    matches!(c as u32, 0x200D | 0xFE0E | 0xFE0F | 0x20E3 | 0x1F3FB..=0x1F3FF | 0xE0020..=0xE007F)
+}
+
+/**
+  Fetches the custom emoji of the guild for `RoleEmoji::is_usable`. If that
+  fails, the error is logged and no custom emoji count as usable, leaving
+  only unicode emoji
+*/
+pub async fn fetch_guild_emojis(http: &HttpClient, guild: GuildId) -> Vec<Emoji> {
+    match http.emojis(guild).await {
+        Ok(emojis) => emojis,
+        Err(e) => {
+            println!("Failed to fetch the emoji of guild {}, only allowing unicode emoji: {:?}", guild, e);
+            Vec::new()
+        }
+    }
+}
+
+impl Display for RoleEmoji {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Custom { id, name, animated: true } => write!(f, "<a:{}:{}>", name, id),
+            Self::Custom { id, name, animated: false } => write!(f, "<:{}:{}>", name, id),
+            Self::Unicode(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unicode(emoji: &str) -> Option<RoleEmoji> {
+        Some(RoleEmoji::Unicode(emoji.to_string()))
+    }
+
+    #[test]
+    fn unicode_emoji_are_parsed() {
+        assert_eq!(RoleEmoji::parse("💻"), unicode("💻"));
+        assert_eq!(RoleEmoji::parse("👩‍💻"), unicode("👩‍💻"));
+        assert_eq!(RoleEmoji::parse("👍🏽"), unicode("👍🏽"));
+        assert_eq!(RoleEmoji::parse("🇸🇪"), unicode("🇸🇪"));
+        assert_eq!(RoleEmoji::parse("✋"), unicode("✋"));
+        assert_eq!(RoleEmoji::parse("❤️"), unicode("❤️"));
+    }
+
+    #[test]
+    fn keycap_emoji_are_parsed() {
+        assert_eq!(RoleEmoji::parse("1️⃣"), unicode("1️⃣"));
+        assert_eq!(RoleEmoji::parse("#️⃣"), unicode("#️⃣"));
+    }
+
+    #[test]
+    fn custom_emoji_are_parsed() {
+        assert_eq!(
+            RoleEmoji::parse("<:blob:123>"),
+            Some(RoleEmoji::Custom { id: EmojiId(123), name: "blob".to_string(), animated: false })
+        );
+        assert_eq!(
+            RoleEmoji::parse("<a:party:456>"),
+            Some(RoleEmoji::Custom { id: EmojiId(456), name: "party".to_string(), animated: true })
+        );
+    }
+
+    #[test]
+    fn text_is_not_an_emoji() {
+        assert_eq!(RoleEmoji::parse(""), None);
+        assert_eq!(RoleEmoji::parse("blob"), None);
+        assert_eq!(RoleEmoji::parse("1"), None);
+        assert_eq!(RoleEmoji::parse(":blob:"), None);
+        assert_eq!(RoleEmoji::parse("<:blob:>"), None);
+        assert_eq!(RoleEmoji::parse("café"), None);
+        assert_eq!(RoleEmoji::parse("é"), None);
+        assert_eq!(RoleEmoji::parse("—"), None);
+        assert_eq!(RoleEmoji::parse("→"), None);
+        assert_eq!(RoleEmoji::parse("\u{FE0F}"), None);
+        assert_eq!(RoleEmoji::parse("💻 x"), None);
+    }
+
+    #[test]
+    fn emoji_display_as_typed() {
+        assert_eq!(RoleEmoji::parse("<a:party:456>").unwrap().to_string(), "<a:party:456>");
+        assert_eq!(RoleEmoji::parse("💻").unwrap().to_string(), "💻");
+    }
+}
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use serde_derive::{Serialize, Deserialize};
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        id::{ChannelId, GuildId, RoleId, UserId},
+        user::User,
+    },
+};
+
+use crate::permissions::{has_permission, permission_denied};
+use crate::role::{RoleError, RoleSource, set_role};
+use crate::role_cache::RoleCache;
+use crate::state::PersistentState;
+use crate::utils::{
+    Result, format_duration, format_timestamp, is_not_found, parse_duration, parse_user,
+    send_alert, send_message, unix_now,
+};
+
+/// How often expired roles are looked for
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A role that should be removed from a user at a given time
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoleExpiry {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    pub role_id: RoleId,
+    pub role_name: String,
+    /// Unix timestamp at which the role is removed
+    pub expires_at: u64,
+}
+
+/**
+  Removes expired roles for as long as the bot runs. The first check runs
+  right away, which catches up on roles that expired while the bot was offline
+*/
+pub async fn run_role_expiry(http: HttpClient) {
+    loop {
+        if let Err(e) = remove_expired_roles(&http).await {
+            println!("Failed to remove expired roles: {:?}", e);
+        }
+        tokio::time::delay_for(EXPIRY_CHECK_INTERVAL).await;
+    }
+}
+
+async fn remove_expired_roles(http: &HttpClient) -> Result<()> {
+    let now = unix_now();
+    let expired = PersistentState::instance().lock().unwrap()
+        .role_expiries.iter()
+        .filter(|expiry| expiry.expires_at <= now)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for expiry in expired {
+        match http.remove_guild_member_role(expiry.guild_id, expiry.user_id, expiry.role_id).await {
+            Ok(_) => {
+                RoleCache::instance().lock().unwrap()
+                    .remove_member_role(expiry.guild_id, expiry.user_id, expiry.role_id);
+                let overdue = now - expiry.expires_at;
+                let late = if overdue > EXPIRY_CHECK_INTERVAL.as_secs() {
+                    format!(" {} late, since I was offline", format_duration(overdue))
+                }
+                else {
+                    String::new()
+                };
+                send_alert(http, format!(
+                    "The role **{}** of <@{}> expired and has been removed{}.",
+                    expiry.role_name, expiry.user_id, late
+                )).await?;
+            }
+            // The member may have left or the role may have been deleted, which
+            // the next attempt would not fix either
+            Err(e) if is_not_found(&e) => println!(
+                "Failed to remove expired role {} from {}, giving up: {:?}",
+                expiry.role_name, expiry.user_id, e
+            ),
+            // Other errors, such as rate limits, may pass, so try again on the next check
+            Err(e) => {
+                println!(
+                    "Failed to remove expired role {} from {}, retrying later: {:?}",
+                    expiry.role_name, expiry.user_id, e
+                );
+                continue;
+            }
+        }
+        let mut ps = PersistentState::instance().lock().unwrap();
+        ps.role_expiries.retain(|other| {
+            !(other.guild_id == expiry.guild_id
+                && other.user_id == expiry.user_id
+                && other.role_id == expiry.role_id
+                && other.expires_at == expiry.expires_at)
+        });
+        ps.save()?;
+    }
+    Ok(())
+}
+
+pub async fn handle_temporary_role<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "temprole").await? {
+        permission_denied(&http, original_channel, author.id, "temprole", "give temporary roles").await?;
+        return Ok(());
+    }
+
+    let usage = "Proper usage: `!temprole <user> <duration> <role name>`, \
+        for example `!temprole @someone 3d Judge`";
+    let (user, duration, role_name) = match rest_command {
+        [user, duration, role_name @ ..] if !role_name.is_empty() => {
+            match (parse_user(&http, guild, user).await?, parse_duration(duration)) {
+                (Some(user), Some(duration)) => (user, duration, role_name.join(" ")),
+                _ => {
+                    send_message(&http, original_channel, author.id, usage).await?;
+                    return Ok(());
+                }
+            }
+        }
+        _ => {
+            send_message(&http, original_channel, author.id, usage).await?;
+            return Ok(());
+        }
+    };
+
+    let expires_at = unix_now() + duration;
+    let reply = match set_role(&http, guild, user, &role_name, RoleSource::Temporary(expires_at)).await {
+        Ok(role) => {
+            println!("{} gave {} the role {} until {}", author.name, user, role, expires_at);
+            format!("<@{}> has the role **{}** until {}.", user, role, format_timestamp(expires_at))
+        }
+        Err(RoleError::AlreadySet(role)) => {
+            println!("{} made the role {} of {} expire at {}", author.name, role, user, expires_at);
+            format!("<@{}> already has the role **{}**, it will be removed {}.", user, role, format_timestamp(expires_at))
+        }
+        Err(RoleError::InvalidName(_)) => format!("The server has no role named **{}**.", role_name),
+        Err(e) => format!("Couldn't give the role: {}", e),
+    };
+    send_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::seq::SliceRandom;
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        id::{ChannelId, GuildId, MessageId, UserId},
+        user::User,
+    },
+};
+
+use crate::capacity::check_usage_thresholds;
+use crate::channel::create_team;
+use crate::lft::member_skills;
+use crate::permissions::{has_permission, permission_denied};
+use crate::state::PersistentState;
+use crate::team_order::sort_team_categories;
+use crate::utils::{
+    Result, fetch_reaction_users, parse_channel, reaction_emoji_string, send_long_message,
+    send_message,
+};
+
+/**
+  Splits the participants into teams of at most `team_size` members, with
+  sizes differing by at most one. Participants with rare skills are placed
+  first so that every team gets a spread of skills.
+
+  Participants should be shuffled beforehand, the placement is deterministic
+*/
+fn form_teams(participants: &[(UserId, Vec<String>)], team_size: usize) -> Vec<Vec<UserId>> {
+    if participants.is_empty() || team_size == 0 {
+        return Vec::new();
+    }
+    let team_count = (participants.len() + team_size - 1) / team_size;
+
+    let mut skill_counts: HashMap<&str, usize> = HashMap::new();
+    for (_, skills) in participants {
+        for skill in skills {
+            *skill_counts.entry(skill).or_insert(0) += 1;
+        }
+    }
+    let rarity = |skills: &Vec<String>| {
+        skills.iter().map(|skill| skill_counts[skill.as_str()]).min().unwrap_or(usize::MAX)
+    };
+    let mut ordered = participants.iter().collect::<Vec<_>>();
+    ordered.sort_by_key(|(_, skills)| rarity(skills));
+
+    let mut teams: Vec<Vec<UserId>> = vec![Vec::new(); team_count];
+    let mut team_skills: Vec<HashMap<&str, usize>> = vec![HashMap::new(); team_count];
+    for (user, skills) in ordered {
+        let smallest = teams.iter().map(Vec::len).min().unwrap();
+        // Among the smallest teams, pick the one with the least overlap in skills
+        let best = (0..team_count)
+            .filter(|team| teams[*team].len() == smallest)
+            .min_by_key(|team| {
+                skills.iter()
+                    .map(|skill| team_skills[*team].get(skill.as_str()).cloned().unwrap_or(0))
+                    .sum::<usize>()
+            })
+            .unwrap();
+        teams[best].push(*user);
+        for skill in skills {
+            *team_skills[best].entry(skill).or_insert(0) += 1;
+        }
+    }
+    teams
+}
+
+/**
+  The number to give the first formed team, which follows the highest
+  existing "Team N" so that earlier runs don't collide with this one
+*/
+fn first_team_number<'a>(game_names: impl Iterator<Item = &'a str>) -> usize {
+    game_names
+        .filter_map(|name| name.to_lowercase().strip_prefix("team ").and_then(|number| number.parse::<usize>().ok()))
+        .max()
+        .map(|highest| highest + 1)
+        .unwrap_or(1)
+}
+
+pub async fn handle_form_teams<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    current_user_id: UserId,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "formteams").await? {
+        permission_denied(&http, original_channel, author.id, "formteams", "form teams").await?;
+        return Ok(());
+    }
+
+    let usage = "Proper usage: `!formteams <channel with the sign-up message> <message ID> \
+        <team size> [create]`\nEveryone who reacted to the sign-up message and is not yet \
+        in a team is placed in a team.";
+    let (channel_id, message_id, team_size, create) = match rest_command {
+        [channel, message, size, rest @ ..] if rest.is_empty() || rest == ["create"] => {
+            match (parse_channel(&http, guild, channel).await?, message.parse::<u64>(), size.parse::<usize>()) {
+                (Some(channel_id), Ok(message_id), Ok(team_size)) if team_size > 0 => {
+                    (channel_id, MessageId(message_id), team_size, !rest.is_empty())
+                }
+                _ => {
+                    send_message(&http, original_channel, author.id, usage).await?;
+                    return Ok(());
+                }
+            }
+        }
+        _ => {
+            send_message(&http, original_channel, author.id, usage).await?;
+            return Ok(());
+        }
+    };
+
+    let sign_up = match http.message(channel_id, message_id).await {
+        Ok(Some(sign_up)) => sign_up,
+        _ => {
+            send_message(&http, original_channel, author.id,
+                format!("No message with ID {} was found in <#{}>", message_id, channel_id)
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    // Everyone who reacted with any emoji has opted in
+    let mut opted_in = HashSet::new();
+    for reaction in &sign_up.reactions {
+        let emoji = reaction_emoji_string(&reaction.emoji);
+        for user in fetch_reaction_users(&http, channel_id, message_id, &emoji).await? {
+            if !user.bot {
+                opted_in.insert(user.id);
+            }
+        }
+    }
+    {
+        let ps = PersistentState::instance().lock().unwrap();
+        opted_in.retain(|user| ps.team_of(*user).is_none());
+    }
+
+    let skills = member_skills(&http, guild).await?;
+    let mut participants = opted_in.into_iter()
+        .map(|user| (user, skills.get(&user).cloned().unwrap_or_default()))
+        .collect::<Vec<_>>();
+    participants.shuffle(&mut rand::thread_rng());
+
+    let teams = form_teams(&participants, team_size);
+    if teams.is_empty() {
+        send_message(&http, original_channel, author.id,
+            "Nobody without a team has signed up yet."
+        ).await?;
+        return Ok(());
+    }
+
+    let first_number = {
+        let ps = PersistentState::instance().lock().unwrap();
+        first_team_number(ps.channel_creators.values().map(|team| team.game_name.as_str()))
+    };
+    let mut lines = Vec::new();
+    for (index, team) in teams.iter().enumerate() {
+        let number = first_number + index;
+        let listed = team.iter()
+            .map(|user| {
+                let user_skills = &skills.get(user).cloned().unwrap_or_default();
+                if user_skills.is_empty() {
+                    format!("<@{}>", user)
+                }
+                else {
+                    format!("<@{}> ({})", user, user_skills.join(", "))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut line = format!("**Team {}**: {}", number, listed);
+
+        if create {
+            let game_name = format!("Team {}", number);
+            match create_team(&game_name, team[1..].to_vec(), guild, team[0], current_user_id, &http).await {
+                Ok(created) => line.push_str(&format!(" - channels: <#{}>", created.text_id)),
+                Err(e) => {
+                    line.push_str(&format!(" - channels not created: {}", e));
+                    println!("Failed to create channels for formed team {}: {:?}", number, e);
+                }
+            }
+        }
+        lines.push(line);
+    }
+
+    if create {
+        if let Err(e) = sort_team_categories(&http, guild).await {
+            println!("Failed to sort team categories: {:?}", e);
+        }
+        if let Err(e) = check_usage_thresholds(&http, guild).await {
+            println!("Failed to check channel usage: {:?}", e);
+        }
+    }
+
+    send_long_message(&http, original_channel, author.id,
+        format!("Formed {} teams of {} jammers:\n{}", teams.len(), participants.len(), lines.join("\n"))
+    ).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn participant(id: u64, skills: &[&str]) -> (UserId, Vec<String>) {
+        (UserId(id), skills.iter().map(|skill| skill.to_string()).collect())
+    }
+
+    fn has_skill(team: &[UserId], participants: &[(UserId, Vec<String>)], skill: &str) -> bool {
+        participants.iter()
+            .any(|(user, skills)| team.contains(user) && skills.iter().any(|other| other == skill))
+    }
+
+    #[test]
+    fn nobody_forms_no_teams() {
+        assert!(form_teams(&[], 3).is_empty());
+        assert!(form_teams(&[participant(1, &[])], 0).is_empty());
+    }
+
+    #[test]
+    fn remainder_is_spread_over_teams() {
+        let participants = (1..=7).map(|id| participant(id, &[])).collect::<Vec<_>>();
+        let teams = form_teams(&participants, 3);
+        let mut sizes = teams.iter().map(Vec::len).collect::<Vec<_>>();
+        sizes.sort();
+        assert_eq!(sizes, vec![2, 2, 3]);
+
+        let mut placed = teams.concat();
+        placed.sort_by_key(|user| user.0);
+        assert_eq!(placed, participants.iter().map(|(user, _)| *user).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn skills_are_spread_over_teams() {
+        let participants = vec![
+            participant(1, &["Programmer"]),
+            participant(2, &["Programmer"]),
+            participant(3, &["Programmer"]),
+            participant(4, &["Artist"]),
+            participant(5, &["Artist"]),
+            participant(6, &["Artist"]),
+        ];
+        let teams = form_teams(&participants, 2);
+        assert_eq!(teams.len(), 3);
+        for team in &teams {
+            assert!(has_skill(team, &participants, "Programmer"));
+            assert!(has_skill(team, &participants, "Artist"));
+        }
+    }
+
+    #[test]
+    fn rare_skills_are_not_doubled_up() {
+        let participants = vec![
+            participant(1, &["Programmer"]),
+            participant(2, &["Programmer"]),
+            participant(3, &["Programmer"]),
+            participant(4, &["Programmer"]),
+            participant(5, &["Composer"]),
+            participant(6, &["Composer"]),
+        ];
+        let teams = form_teams(&participants, 3);
+        for team in &teams {
+            assert!(has_skill(team, &participants, "Composer"));
+        }
+    }
+
+    #[test]
+    fn team_numbers_follow_the_highest_existing_one() {
+        assert_eq!(first_team_number(["Cool Game"].iter().cloned()), 1);
+        assert_eq!(first_team_number(["Team 1", "team 3", "Team X", "Cool Game"].iter().cloned()), 4);
+        assert_eq!(first_team_number(std::iter::empty()), 1);
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use serde_derive::{Serialize, Deserialize};
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        guild::Member,
+        id::{ChannelId, GuildId, RoleId, UserId},
+        user::User,
+    },
+};
+
+use crate::role::RoleError;
+use crate::role_cache::guild_roles;
+use crate::roles::RoleMatch;
+use crate::state::PersistentState;
+use crate::utils::{Result, fetch_all_members, send_long_message, send_message, unix_now};
+
+/// A solo jammer's post on the looking-for-team board
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LftEntry {
+    pub note: String,
+    pub posted_at: u64,
+}
+
+impl PersistentState {
+    /// Removes users who have joined a team from the looking-for-team board
+    pub fn expire_lft_entries(&mut self) -> Result<()> {
+        let before = self.looking_for_team.len();
+        let in_team = self.looking_for_team.keys()
+            .filter(|user| self.team_of(**user).is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+        for user in in_team {
+            self.looking_for_team.remove(&user);
+        }
+        if self.looking_for_team.len() != before {
+            self.save()?;
+        }
+        Ok(())
+    }
+}
+
+/**
+  Maps the guild's skill roles to their name in the requestable role
+  registry, so that differences in case between the two don't matter
+*/
+async fn skill_role_names(http: &HttpClient, guild: GuildId) -> Result<HashMap<RoleId, String>> {
+    let roles = guild_roles(http, guild).await?;
+    let ps = PersistentState::instance().lock().unwrap();
+    Ok(roles.into_iter()
+        .filter_map(|role| ps.find_requestable_role(&role.name).map(|requestable| (role.id, requestable.name.clone())))
+        .collect())
+}
+
+fn skills_of(member: &Member, role_names: &HashMap<RoleId, String>) -> Vec<String> {
+    member.roles.iter()
+        .filter_map(|role| role_names.get(role).cloned())
+        .collect()
+}
+
+/// Checks if the skills contain the role, ignoring case
+fn has_skill(skills: &[String], role: &str) -> bool {
+    skills.iter().any(|skill| skill.eq_ignore_ascii_case(role))
+}
+
+/// Fetches the names of the skill roles held by every member of the guild
+pub async fn member_skills(http: &HttpClient, guild: GuildId) -> Result<HashMap<UserId, Vec<String>>> {
+    let role_names = skill_role_names(http, guild).await?;
+    Ok(fetch_all_members(http, guild).await?
+        .iter()
+        .map(|member| (member.user.id, skills_of(member, &role_names)))
+        .collect())
+}
+
+pub async fn handle_looking_for_team<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    let reply = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        if rest_command == ["off"] {
+            ps.looking_for_team.remove(&author.id);
+            ps.save()?;
+            "You have been removed from the looking-for-team board.".to_string()
+        }
+        else if ps.team_of(author.id).is_some() {
+            "You are already part of a team.".to_string()
+        }
+        else {
+            ps.looking_for_team.insert(author.id, LftEntry {
+                note: rest_command.join(" "),
+                posted_at: unix_now(),
+            });
+            ps.save()?;
+            "You are now on the looking-for-team board. Teams looking for your skills \
+            can find you with `!matches`, and you can find them the same way.\n\
+            Leave the board with `!lft off`. You are removed automatically when you join a team.".to_string()
+        }
+    };
+    send_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
+
+pub async fn handle_looking_for_members<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    let creator = PersistentState::instance().lock().unwrap().team_of(author.id);
+    let creator = match creator {
+        Some(creator) => creator,
+        None => {
+            send_message(&http, original_channel, author.id,
+                "You need to be part of a team to look for members."
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let requested = rest_command.join(" ");
+    let needed_roles = if requested == "off" {
+        Vec::new()
+    }
+    else {
+        let mut needed_roles = Vec::new();
+        for name in requested.split(|c| c == ',' || c == ';').map(str::trim).filter(|name| !name.is_empty()) {
+            let role_match = PersistentState::instance().lock().unwrap().match_requestable_role(name);
+            match role_match {
+                RoleMatch::Found(role) => needed_roles.push(role),
+                RoleMatch::Ambiguous(candidates) => {
+                    send_message(&http, original_channel, author.id,
+                        format!("{}", RoleError::Ambiguous(candidates))
+                    ).await?;
+                    return Ok(());
+                }
+                RoleMatch::NotFound => {
+                    send_message(&http, original_channel, author.id,
+                        format!(
+                            "**{}** is not a skill role.\n\
+                            Proper usage: `!lfm <role>, <role>, ...` or `!lfm off`", name
+                        )
+                    ).await?;
+                    return Ok(());
+                }
+            }
+        }
+        needed_roles
+    };
+
+    let reply = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let team = ps.channel_creators.get_mut(&creator).unwrap();
+        team.needed_roles = needed_roles.clone();
+        let game_name = team.game_name.clone();
+        ps.save()?;
+        if needed_roles.is_empty() {
+            format!("**{}** is no longer looking for members.", game_name)
+        }
+        else {
+            format!(
+                "**{}** is now looking for: {}. Use `!matches` to find jammers with those skills.",
+                game_name, needed_roles.join(", ")
+            )
+        }
+    };
+    send_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
+
+pub async fn handle_matches(
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    PersistentState::instance().lock().unwrap().expire_lft_entries()?;
+    let role_names = skill_role_names(&http, guild).await?;
+    let members = fetch_all_members(&http, guild).await?;
+    let skills = members.iter()
+        .map(|member| (member.user.id, skills_of(member, &role_names)))
+        .collect::<HashMap<_, _>>();
+    // Listed jammers are named rather than mentioned, so asking for matches doesn't ping them all
+    let names = members.iter()
+        .map(|member| (member.user.id, format!("{}#{}", member.user.name, member.user.discriminator)))
+        .collect::<HashMap<_, _>>();
+    let name_of = |user: &UserId| names.get(user).cloned().unwrap_or_else(|| format!("user {}", user));
+
+    let message = {
+        let ps = PersistentState::instance().lock().unwrap();
+        match ps.team_of(author.id) {
+            // Suggest solo jammers with the skills the team needs
+            Some(creator) => {
+                let team = &ps.channel_creators[&creator];
+                if team.needed_roles.is_empty() {
+                    "Your team has not listed any skills it needs. Use `!lfm <role>, <role>, ...` first.".to_string()
+                }
+                else {
+                    let suggestions = team.needed_roles.iter()
+                        .map(|needed| {
+                            let users = ps.looking_for_team.iter()
+                                .filter(|(user, _)| skills.get(*user).map(|s| has_skill(s, needed)).unwrap_or(false))
+                                .map(|(user, entry)| {
+                                    if entry.note.is_empty() {
+                                        format!("**{}**", name_of(user))
+                                    }
+                                    else {
+                                        format!("**{}** ({})", name_of(user), entry.note)
+                                    }
+                                })
+                                .collect::<Vec<_>>();
+                            if users.is_empty() {
+                                format!("**{}**: nobody yet", needed)
+                            }
+                            else {
+                                format!("**{}**: {}", needed, users.join(", "))
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    format!("Solo jammers matching what **{}** needs:\n{}", team.game_name, suggestions)
+                }
+            }
+            // Suggest teams that need the author's skills
+            None => {
+                let own_skills = skills.get(&author.id).cloned().unwrap_or_default();
+                let teams = ps.channel_creators.values()
+                    .filter_map(|team| {
+                        let wanted = team.needed_roles.iter()
+                            .filter(|needed| has_skill(&own_skills, needed))
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        if wanted.is_empty() {
+                            None
+                        }
+                        else {
+                            Some(format!("**{}** (<#{}>) needs {}", team.game_name, team.text_id, wanted.join(", ")))
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                if own_skills.is_empty() {
+                    "You have no skill roles yet. Get some with `!role <role name>` first.".to_string()
+                }
+                else if teams.is_empty() {
+                    "No teams are currently looking for your skills.".to_string()
+                }
+                else {
+                    format!("Teams looking for your skills:\n{}", teams.join("\n"))
+                }
+            }
+        }
+    };
+    send_long_message(&http, original_channel, author.id, message).await?;
+    Ok(())
+}
@@ -17,20 +17,55 @@ use twilight::{
     },
 };
 
+mod capacity;
 mod channel;
+mod devlog;
+mod emoji;
+mod expiry;
+mod formation;
+mod lft;
+mod naming;
+mod permissions;
 mod reaction;
+mod recruit;
 mod role;
+mod role_cache;
 mod roles;
+mod setup;
 mod state;
+mod stats;
+mod submission;
+mod sync;
+mod team_order;
 mod theme;
 mod utils;
+mod voice;
 
+use capacity::{handle_channel_usage, handle_set_alert_channel, handle_set_channel_budget};
 use channel::{handle_create_channels, handle_remove_channels, handle_rename_channels};
-use reaction::{handle_reaction_add, handle_reaction_remove, handle_set_reaction_message, ReactionMessageType};
-use role::{handle_give_role, handle_remove_role, has_role};
-use roles::ORGANIZER;
+use devlog::{handle_export_devlogs, handle_potential_devlog_entry, handle_set_checkins, run_checkin_scheduler};
+use expiry::{handle_temporary_role, run_role_expiry};
+use formation::handle_form_teams;
+use lft::{handle_looking_for_members, handle_looking_for_team, handle_matches};
+use permissions::{format_permitted_commands, handle_set_permission};
+use reaction::{
+    handle_bind_emoji, handle_list_reaction_messages, handle_pm_reaction_add, handle_post_role_message,
+    handle_reaction_add, handle_reaction_remove, handle_remove_reaction_message,
+    handle_set_reaction_message, handle_unbind_emoji, ReactionMessageType,
+};
+use role::{
+    handle_add_requestable_role, handle_delete_requestable_role, handle_give_role,
+    handle_remove_role, handle_set_role_group, handle_set_role_style,
+};
+use role_cache::RoleCache;
+use setup::{handle_guild_available, handle_setup};
+use stats::handle_role_stats;
+use submission::{handle_list_games, handle_set_deadline, handle_submit_game};
+use sync::{handle_sync_roles, sync_after_downtime};
+use team_order::{handle_set_team_markers, handle_sort_teams};
 use theme::{handle_add_theme, handle_generate_theme, handle_show_all_themes};
 use utils::{Result, send_message};
+use voice::{handle_set_voice_config, handle_voice_limit};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -42,10 +77,13 @@ async fn main() -> Result<()> {
 
     let config = ClusterConfig::builder(&token)
         .shard_scheme(scheme)
-        // Use intents to only listen to GUILD_MESSAGES events
+        // Use intents to only listen to the events we handle
         .intents(Some(
-            GatewayIntents::GUILD_MESSAGES
+            GatewayIntents::GUILDS
+                | GatewayIntents::GUILD_MEMBERS
+                | GatewayIntents::GUILD_MESSAGES
                 | GatewayIntents::DIRECT_MESSAGES
+                | GatewayIntents::DIRECT_MESSAGE_REACTIONS
                 | GatewayIntents::GUILD_MESSAGE_REACTIONS,
         ))
         .build();
@@ -73,6 +111,9 @@ async fn main() -> Result<()> {
     let cache = InMemoryCache::from(cache_config);
 
 
+    tokio::spawn(run_checkin_scheduler(http.clone()));
+    tokio::spawn(run_role_expiry(http.clone()));
+
     let mut events = cluster.events().await;
 
     let current_user = http.current_user().await?;
@@ -80,6 +121,7 @@ async fn main() -> Result<()> {
     while let Some(event) = events.next().await {
         // Update the cache
         cache.update(&event.1).await.expect("Cache failed, OhNoe!");
+        RoleCache::instance().lock().unwrap().update(&event.1);
 
         // Spawn a new task to handle the event
         handle_event(event, http.clone(), &current_user).await?;
@@ -118,12 +160,29 @@ async fn handle_event(
             if !is_pm(&http, reaction.channel_id).await? {
                 handle_reaction_add(&reaction, http, &current_user).await?;
             }
+            else {
+                handle_pm_reaction_add(&reaction, http, &current_user).await?;
+            }
         }
         (_, Event::ReactionRemove(reaction)) => {
             if !is_pm(&http, reaction.channel_id).await? {
                 handle_reaction_remove(&reaction, http).await?;
             }
         }
+        (_, Event::GuildCreate(guild)) => {
+            // Make sure all roles exist whenever the bot joins or reconnects to a guild
+            if let Err(e) = handle_guild_available(&http, guild.id).await {
+                println!("Failed to provision roles in guild {}: {:?}", guild.id, e);
+            }
+            // Catch up on reactions added or removed while the bot was offline. This fetches
+            // every reaction, so don't hold up other events meanwhile
+            let guild_id = guild.id;
+            tokio::spawn(async move {
+                if let Err(e) = sync_after_downtime(&http, guild_id).await {
+                    println!("Failed to sync roles with reactions in guild {}: {:?}", guild_id, e);
+                }
+            });
+        }
         (id, Event::ShardConnected(_)) => {
             println!("Connected on shard {}", id);
         }
@@ -171,11 +230,30 @@ async fn handle_potential_command(
             handle_rename_channels(
                 &words.collect::<Vec<_>>(),
                 msg.channel_id,
+                msg.guild_id.expect("Tried to rename channels in non-guild"),
                 msg.author.id,
-                current_user.id,
                 http
             ).await?;
         },
+        Some("!voicelimit") => {
+            handle_voice_limit(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                &msg.author,
+                http
+            ).await?;
+        },
+        Some("!setrecruit") => {
+            handle_set_reaction_message(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to set team recruiting post in non-guild"),
+                &msg.author,
+                http,
+                msg,
+                ReactionMessageType::TeamRecruit,
+            ).await?;
+        },
         Some("!removechannels") => {
             handle_remove_channels(
                 &words.collect::<Vec<_>>(),
@@ -185,6 +263,45 @@ async fn handle_potential_command(
                 http
             ).await?;
         },
+        Some("!submitgame") => {
+            handle_submit_game(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                &msg.author,
+                http
+            ).await?;
+        },
+        Some("!games") => {
+            handle_list_games(
+                msg.channel_id,
+                &msg.author,
+                http
+            ).await?;
+        },
+        Some("!lft") => {
+            handle_looking_for_team(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                &msg.author,
+                http
+            ).await?;
+        },
+        Some("!lfm") => {
+            handle_looking_for_members(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                &msg.author,
+                http
+            ).await?;
+        },
+        Some("!matches") => {
+            handle_matches(
+                msg.channel_id,
+                msg.guild_id.expect("Tried to find matches in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        },
         Some("!role") => {
             handle_give_role(
                 &words.collect::<Vec<_>>(),
@@ -230,6 +347,218 @@ async fn handle_potential_command(
                 ReactionMessageType::RoleAssign,
             ).await?;
         }
+        Some("!postroleassign") => {
+            handle_post_role_message(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to post a role assignment message in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!syncroles") => {
+            handle_sync_roles(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to sync roles in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!rolemessages") => {
+            handle_list_reaction_messages(
+                msg.channel_id,
+                msg.guild_id.expect("Tried to list role assignment messages in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!bindemoji") => {
+            handle_bind_emoji(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to bind an emoji in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!unbindemoji") => {
+            handle_unbind_emoji(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to unbind an emoji in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!removerolemessage") => {
+            handle_remove_reaction_message(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to remove a role assignment message in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!channelusage") => {
+            handle_channel_usage(
+                msg.channel_id,
+                msg.guild_id.expect("Tried to view channel usage in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!setchannelbudget") => {
+            handle_set_channel_budget(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to set channel budget in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!setalertchannel") => {
+            handle_set_alert_channel(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to set alert channel in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!setvoiceconfig") => {
+            handle_set_voice_config(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to configure voice channels in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!sortteams") => {
+            handle_sort_teams(
+                msg.channel_id,
+                msg.guild_id.expect("Tried to sort teams in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!setteammarkers") => {
+            handle_set_team_markers(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to set team markers in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!setdeadline") => {
+            handle_set_deadline(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to set deadline in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!formteams") => {
+            handle_form_teams(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to form teams in non-guild"),
+                &msg.author,
+                current_user.id,
+                http
+            ).await?;
+        }
+        Some("!setcheckins") => {
+            handle_set_checkins(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to schedule check-ins in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!exportdevlogs") => {
+            handle_export_devlogs(
+                msg.channel_id,
+                msg.guild_id.expect("Tried to export devlogs in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!addrole") => {
+            handle_add_requestable_role(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to add a requestable role in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!delrole") => {
+            handle_delete_requestable_role(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to remove a requestable role in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!setup") => {
+            handle_setup(
+                msg.channel_id,
+                msg.guild_id.expect("Tried to run setup in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!rolegroup") => {
+            handle_set_role_group(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to group roles in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!setrolestyle") => {
+            handle_set_role_style(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to change a role style in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!rolestats") => {
+            handle_role_stats(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to view role statistics in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!temprole") => {
+            handle_temporary_role(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to give a temporary role in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
+        Some("!setpermission") => {
+            handle_set_permission(
+                &words.collect::<Vec<_>>(),
+                msg.channel_id,
+                msg.guild_id.expect("Tried to set command permissions in non-guild"),
+                &msg.author,
+                http
+            ).await?;
+        }
         Some(s) if s.chars().next() == Some('!') => {
             send_message(&http, msg.channel_id, msg.author.id,
                 format!("Unrecognised command `{}`.", s)
@@ -243,6 +572,10 @@ async fn handle_potential_command(
         }
         // Not a command and probably not for us
         Some(_) => {
+            if let Err(e) = handle_potential_devlog_entry(msg) {
+                println!("Failed to record devlog entry: {:?}", e);
+            }
+
             // Check if we were mentioned
             if msg.mentions.contains_key(&current_user.id) {
                 send_help_message(
@@ -266,26 +599,28 @@ async fn send_help_message(
 ) -> Result<()> {
     let standard_message =
         "Send me a PM to submit theme ideas.\n\n\
-        Get a role to signify one of your skill sets with the command `!role <role name>`\n\
-        and leave a role with `!leave <role name>`.\n\n\
+        Get roles to signify your skill sets with the command `!role <role name>, <role name>, ...`\n\
+        and leave roles the same way with `!leave <role name>, ...`.\n\n\
         You can also ask for text and voice channels for your game \
         with the command `!createchannels <game name>`\n\
-        and rename them with `!renamechannels <new game name>`.";
-    let organizer_message = format!(
-        "Since you have the **{}** role, you also have access to the \
-        following commands:\n\
-        - `!generatetheme` to generate a theme.\n\
-        - `!showallthemes` to view all the theme ideas that have been submitted.\n\
-        - `!removechannels <mention of user>` to remove a user's created channel.\n\
-        - `!setroleassign <mention of channel with the message> <message ID>` to \
-        set the server's role assignment message.", ORGANIZER
-    );
+        and rename them with `!renamechannels <new game name>`.\n\
+        Limit how many can join your voice channel with `!voicelimit <number of users>`.\n\
+        Recruit with `!setrecruit <channel> <message ID>` on a post of yours, \
+        people who react with ✋ ask to join your team.\n\
+        Submit your team's game with `!submitgame <link> [description]` \
+        and see all submitted games with `!games`.\n\n\
+        Looking for a team? Post on the board with `!lft [note]`. Teams can list the \
+        skills they need with `!lfm <role>, <role>, ...` and everyone can find matches with `!matches`.";
+    let permitted_commands = format_permitted_commands(&http, guild_id, user_id).await?;
     let help_message =
-    if has_role(&http, guild_id, user_id, ORGANIZER).await? {
-        format!("{}\n\n{}", standard_message, organizer_message)
+    if permitted_commands.is_empty() {
+        standard_message.to_string()
     }
     else {
-        standard_message.to_string()
+        format!(
+            "{}\n\nWith your roles, you also have access to the following commands:\n{}",
+            standard_message, permitted_commands.join("\n")
+        )
     };
     send_message(&http, channel_id, user_id, help_message).await?;
     Ok(())
@@ -0,0 +1,215 @@
+use std::fmt::Display;
+
+use unicode_normalization::UnicodeNormalization;
+
+/// The longest channel or category name Discord accepts
+pub const MAX_NAME_LENGTH: usize = 100;
+const CATEGORY_PREFIX: &str = "Team: ";
+
+/**
+  The names of all channels belonging to a team, derived from the game name
+  such that Discord accepts them as they are
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelNames {
+    /// The normalized game name, used in messages and stored in the team
+    pub display: String,
+    /// Lowercase text channel slug, e.g. `my-cool-game`
+    pub text: String,
+    pub voice: String,
+    pub category: String,
+}
+
+/**
+  Error type for name derivation
+
+  The Display implementation is intended to be sent back to the user
+*/
+#[derive(Debug, PartialEq)]
+pub enum NameError {
+    /// Nothing was left of the name after normalization
+    Empty,
+    /// The name has no characters that are allowed in a text channel name,
+    /// for example when it only consists of emoji
+    NoTextCharacters,
+}
+
+impl Display for NameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Self::Empty => "You need to specify a game name.",
+            Self::NoTextCharacters =>
+                "Game names need at least one letter or digit to be used as a text channel name.",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::error::Error for NameError {}
+
+/// Derives text, voice and category names for the channels of a game
+pub fn derive_names(game_name: &str) -> std::result::Result<ChannelNames, NameError> {
+    let display = truncate(&normalize(game_name), MAX_NAME_LENGTH);
+    if display.is_empty() {
+        return Err(NameError::Empty);
+    }
+
+    let text = text_channel_slug(&display);
+    if text.is_empty() {
+        return Err(NameError::NoTextCharacters);
+    }
+
+    Ok(ChannelNames {
+        voice: display.clone(),
+        category: truncate(&format!("{}{}", CATEGORY_PREFIX, display), MAX_NAME_LENGTH),
+        text,
+        display,
+    })
+}
+
+/**
+  Applies NFKC normalization, drops control characters and the markdown
+  breaking characters ` and |, and collapses all whitespace into single spaces
+*/
+fn normalize(name: &str) -> String {
+    name.nfkc()
+        .filter(|c| !c.is_control() && *c != '`' && *c != '|')
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/**
+  Builds a name following Discord's rules for text channels: lowercase,
+  no spaces, and only letters, digits, dashes and underscores
+*/
+fn text_channel_slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if (c.is_alphanumeric() && !is_emoji(c)) || c == '_' {
+            slug.push(c);
+        }
+        else if (c.is_whitespace() || c == '-') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    truncate(&slug, MAX_NAME_LENGTH).trim_end_matches('-').to_string()
+}
+
+/// Checks if the character is part of an emoji, including the joiners and
+/// modifiers used to build emoji sequences
+fn is_emoji(c: char) -> bool {
+    match c as u32 {
+        0x1F000..=0x1FAFF // Pictographs, emoticons, flags, skin tones, ...
+            | 0x2600..=0x27BF // Miscellaneous symbols and dingbats
+            | 0x2B00..=0x2BFF // Arrows and stars like ⭐
+            | 0x200D // Zero width joiner
+            | 0x20E3 // Combining enclosing keycap
+            | 0xFE00..=0xFE0F // Variation selectors
+            | 0xE0020..=0xE007F // Tags used in subdivision flags
+            => true,
+        _ => false,
+    }
+}
+
+/**
+  Cuts the string to at most `max_length` characters without leaving
+  whitespace or a dangling part of an emoji sequence at the end
+*/
+fn truncate(name: &str, max_length: usize) -> String {
+    if name.chars().count() <= max_length {
+        name.to_string()
+    }
+    else {
+        name.chars()
+            .take(max_length)
+            .collect::<String>()
+            .trim_end_matches(|c: char| c.is_whitespace() || c == '\u{200D}' || c == '\u{FE0F}')
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple_names_are_slugified() {
+        let names = derive_names("My Cool Game").unwrap();
+        assert_eq!(names.display, "My Cool Game");
+        assert_eq!(names.text, "my-cool-game");
+        assert_eq!(names.voice, "My Cool Game");
+        assert_eq!(names.category, "Team: My Cool Game");
+    }
+
+    #[test]
+    fn whitespace_is_collapsed() {
+        let names = derive_names("  Space \t  Game\n ").unwrap();
+        assert_eq!(names.display, "Space Game");
+        assert_eq!(names.text, "space-game");
+    }
+
+    #[test]
+    fn punctuation_is_removed_from_text_channel() {
+        let names = derive_names("Rock'n'Roll: The Game!").unwrap();
+        assert_eq!(names.text, "rocknroll-the-game");
+        assert_eq!(names.voice, "Rock'n'Roll: The Game!");
+    }
+
+    #[test]
+    fn dashes_and_underscores_are_kept() {
+        assert_eq!(derive_names("super_game - 2").unwrap().text, "super_game-2");
+        assert_eq!(derive_names("--edgy--").unwrap().text, "edgy");
+    }
+
+    #[test]
+    fn markdown_breaking_characters_are_removed() {
+        let names = derive_names("`Pipe|Dream`").unwrap();
+        assert_eq!(names.display, "PipeDream");
+        assert_eq!(names.text, "pipedream");
+    }
+
+    #[test]
+    fn emoji_are_kept_in_voice_but_not_text() {
+        let names = derive_names("🚀 Rocket 👨‍👩‍👧 Game ⭐").unwrap();
+        assert_eq!(names.voice, "🚀 Rocket 👨‍👩‍👧 Game ⭐");
+        assert_eq!(names.text, "rocket-game");
+    }
+
+    #[test]
+    fn emoji_only_names_are_rejected() {
+        assert_eq!(derive_names("🚀🎮"), Err(NameError::NoTextCharacters));
+    }
+
+    #[test]
+    fn empty_names_are_rejected() {
+        assert_eq!(derive_names(""), Err(NameError::Empty));
+        assert_eq!(derive_names(" \u{7} "), Err(NameError::Empty));
+    }
+
+    #[test]
+    fn unicode_is_normalized() {
+        // Fullwidth letters and a decomposed "é"
+        let names = derive_names("Ｇａｍｅ Cafe\u{301}").unwrap();
+        assert_eq!(names.display, "Game Café");
+        assert_eq!(names.text, "game-café");
+    }
+
+    #[test]
+    fn non_latin_letters_are_allowed() {
+        assert_eq!(derive_names("Игра Ёж").unwrap().text, "игра-ёж");
+    }
+
+    #[test]
+    fn long_names_are_truncated() {
+        let long_name = "word ".repeat(40);
+        let names = derive_names(&long_name).unwrap();
+        assert!(names.text.chars().count() <= MAX_NAME_LENGTH);
+        assert!(names.voice.chars().count() <= MAX_NAME_LENGTH);
+        assert!(names.category.chars().count() <= MAX_NAME_LENGTH);
+        assert!(!names.text.ends_with('-'));
+        assert!(!names.voice.ends_with(' '));
+        assert!(names.category.starts_with("Team: word"));
+    }
+}
@@ -0,0 +1,244 @@
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        guild::Role,
+        id::{ChannelId, GuildId, RoleId, UserId},
+        user::User,
+    },
+};
+
+use crate::role::split_role_list;
+use crate::role_cache::{guild_roles, member_roles};
+use crate::roles::ORGANIZER;
+use crate::state::PersistentState;
+use crate::utils::{Result, send_message};
+
+/// A command that only members with one of its allowed roles may use
+pub struct PrivilegedCommand {
+    /// The command without the leading `!`
+    pub name: &'static str,
+    pub usage: &'static str,
+    /// Finishes the sentence "... to ..." in the help message
+    pub description: &'static str,
+}
+
+impl PrivilegedCommand {
+    const fn new(name: &'static str, usage: &'static str, description: &'static str) -> Self {
+        Self { name, usage, description }
+    }
+}
+
+/// Every privileged command, in the order they are listed in the help message
+pub const PRIVILEGED_COMMANDS: &[PrivilegedCommand] = &[
+    PrivilegedCommand::new("generatetheme", "!generatetheme", "generate a theme"),
+    PrivilegedCommand::new("showallthemes", "!showallthemes", "view all the theme ideas that have been submitted"),
+    PrivilegedCommand::new("removechannels", "!removechannels <user>", "remove a user's created channel"),
+    PrivilegedCommand::new("setroleassign", "!setroleassign <channel with the message> <message ID> [role category]",
+        "add a role assignment message for every role, or the roles of one category"),
+    PrivilegedCommand::new("postroleassign", "!postroleassign <channel> [role category]",
+        "post a role assignment message that I keep up to date"),
+    PrivilegedCommand::new("rolemessages", "!rolemessages", "list the role assignment messages and their emoji"),
+    PrivilegedCommand::new("bindemoji", "!bindemoji [message ID] <emoji> <role name>",
+        "make reacting with an emoji on a role assignment message give a requestable role"),
+    PrivilegedCommand::new("unbindemoji", "!unbindemoji [message ID] <emoji>",
+        "make an emoji on a role assignment message no longer give a role"),
+    PrivilegedCommand::new("syncroles", "!syncroles [apply]",
+        "list the differences between roles and the reactions on the role assignment messages, or fix them"),
+    PrivilegedCommand::new("removerolemessage", "!removerolemessage <message ID>",
+        "stop a message from assigning roles"),
+    PrivilegedCommand::new("channelusage", "!channelusage", "see how many channels the server and its teams use"),
+    PrivilegedCommand::new("setchannelbudget", "!setchannelbudget <number of channels>",
+        "limit the channels teams may fill up"),
+    PrivilegedCommand::new("setalertchannel", "!setalertchannel <channel>", "choose where organizer alerts are posted"),
+    PrivilegedCommand::new("setvoiceconfig", "!setvoiceconfig <limit|bitrate|region> <value|default>",
+        "configure team voice channels"),
+    PrivilegedCommand::new("setteammarkers", "!setteammarkers <category> | <category>",
+        "set the categories team categories are placed between"),
+    PrivilegedCommand::new("sortteams", "!sortteams", "sort the team categories alphabetically"),
+    PrivilegedCommand::new("setdeadline", "!setdeadline <unix timestamp|+duration|none>",
+        "set the game submission deadline"),
+    PrivilegedCommand::new("formteams", "!formteams <channel> <message ID> <team size> [create]",
+        "form teams of everyone who reacted to a sign-up message, optionally creating their channels"),
+    PrivilegedCommand::new("setcheckins", "!setcheckins <interval> [prompt]",
+        "regularly ask teams for progress updates"),
+    PrivilegedCommand::new("exportdevlogs", "!exportdevlogs", "view the progress updates collected from all teams"),
+    PrivilegedCommand::new("addrole", "!addrole <role name> | <emoji or -> | <category> | <description>",
+        "make a role requestable"),
+    PrivilegedCommand::new("delrole", "!delrole <role name>", "make a role no longer requestable"),
+    PrivilegedCommand::new("rolegroup", "!rolegroup <role name> | <group name or none>",
+        "make roles of a group mutually exclusive"),
+    PrivilegedCommand::new("setrolestyle", "!setrolestyle <role name> | <color or -> | <mentionable|unmentionable>",
+        "choose the color and mentionability of a role"),
+    PrivilegedCommand::new("setup", "!setup", "create any of the bot's roles that are missing on the server"),
+    PrivilegedCommand::new("rolestats", "!rolestats [csv]",
+        "see how many members have each role, or export the sign-up history"),
+    PrivilegedCommand::new("temprole", "!temprole <user> <duration> <role name>",
+        "give a role that is removed automatically"),
+    PrivilegedCommand::new("setpermission", "!setpermission <command> <role>, <role>, ...|default",
+        "choose which roles may use a command"),
+];
+
+fn find_command(name: &str) -> Option<&'static PrivilegedCommand> {
+    let name = name.trim_start_matches('!').to_lowercase();
+    PRIVILEGED_COMMANDS.iter().find(|command| command.name == name)
+}
+
+impl PersistentState {
+    /// The roles allowed to use the command. Only organizers unless configured otherwise
+    pub fn allowed_roles(&self, command: &str) -> Vec<String> {
+        self.command_permissions.get(command)
+            .cloned()
+            .unwrap_or_else(|| vec![ORGANIZER.to_string()])
+    }
+}
+
+/// Formats the allowed roles of the command as `**A**, **B** or **C**`
+fn format_allowed_roles(command: &str) -> String {
+    let roles = PersistentState::instance().lock().unwrap()
+        .allowed_roles(command).iter()
+        .map(|role| format!("**{}**", role))
+        .collect::<Vec<_>>();
+    match roles.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        _ => roles.join(""),
+    }
+}
+
+/// Resolves the allowed roles of the command to the IDs of the guild's roles with those names
+fn allowed_role_ids(command: &str, guild_roles: &[Role]) -> Vec<RoleId> {
+    let allowed = PersistentState::instance().lock().unwrap().allowed_roles(command);
+    guild_roles.iter()
+        .filter(|role| allowed.iter().any(|name| name.to_lowercase() == role.name.to_lowercase()))
+        .map(|role| role.id)
+        .collect()
+}
+
+fn is_allowed(command: &str, guild_roles: &[Role], user_roles: &[RoleId]) -> bool {
+    allowed_role_ids(command, guild_roles).iter().any(|role| user_roles.contains(role))
+}
+
+/// Checks if the user has one of the roles allowed to use the command
+pub async fn has_permission(
+    http: &HttpClient,
+    guild_id: GuildId,
+    user_id: UserId,
+    command: &str,
+) -> Result<bool> {
+    let guild_roles = guild_roles(http, guild_id).await?;
+    let user_roles = member_roles(http, guild_id, user_id).await?;
+    Ok(is_allowed(command, &guild_roles, &user_roles))
+}
+
+/**
+  Tells the user that they may not use the command. `action` finishes the
+  sentence "you do not have permission to ..."
+*/
+pub async fn permission_denied(
+    http: &HttpClient,
+    channel_id: ChannelId,
+    user_id: UserId,
+    command: &str,
+    action: &str,
+) -> Result<()> {
+    let allowed = format_allowed_roles(command);
+    send_message(http, channel_id, user_id,
+        format!(
+            "Since you lack the required role {}, you do \
+            not have permission to {}.", allowed, action)
+    ).await?;
+    println!("Tried to {} without required role {}", action, allowed);
+    Ok(())
+}
+
+/// Lists the privileged commands the user may use, for the help message
+pub async fn format_permitted_commands(
+    http: &HttpClient,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> Result<Vec<String>> {
+    let guild_roles = guild_roles(http, guild_id).await?;
+    let user_roles = member_roles(http, guild_id, user_id).await?;
+    Ok(PRIVILEGED_COMMANDS.iter()
+        .filter(|command| is_allowed(command.name, &guild_roles, &user_roles))
+        .map(|command| format!("- `{}` to {}.", command.usage, command.description))
+        .collect())
+}
+
+pub async fn handle_set_permission<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    // The server owner may always change permissions, so nobody can be locked out
+    let is_owner = http.guild(guild).await?
+        .map(|guild| guild.owner_id == author.id)
+        .unwrap_or(false);
+    if !is_owner && !has_permission(&http, guild, author.id, "setpermission").await? {
+        permission_denied(&http, original_channel, author.id, "setpermission", "change command permissions").await?;
+        return Ok(());
+    }
+
+    let command = match rest_command.get(0) {
+        Some(name) => find_command(name),
+        None => {
+            let lines = PRIVILEGED_COMMANDS.iter()
+                .map(|command| format!("`!{}`: {}", command.name, format_allowed_roles(command.name)))
+                .collect::<Vec<_>>();
+            send_message(&http, original_channel, author.id,
+                format!(
+                    "Roles allowed to use each command:\n{}\n\nChange them with \
+                    `!setpermission <command> <role>, <role>, ...` or `!setpermission <command> default`.",
+                    lines.join("\n")
+                )
+            ).await?;
+            return Ok(());
+        }
+    };
+    let command = match command {
+        Some(command) => command,
+        None => {
+            send_message(&http, original_channel, author.id,
+                format!("`{}` is not a command that needs permission.", rest_command[0])
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let roles = split_role_list(&rest_command[1..].join(" "));
+    if roles.is_empty() {
+        send_message(&http, original_channel, author.id,
+            "Proper usage: `!setpermission <command> <role>, <role>, ...` or \
+            `!setpermission <command> default`, for example `!setpermission removechannels Organizer, Moderator`"
+        ).await?;
+        return Ok(());
+    }
+    let reset = roles.len() == 1 && roles[0].eq_ignore_ascii_case("default");
+
+    // Warn about roles that don't exist, they may be created later on
+    let existing_roles = guild_roles(&http, guild).await?;
+    let unknown = roles.iter()
+        .filter(|role| !reset && !existing_roles.iter().any(|existing| existing.name.to_lowercase() == role.to_lowercase()))
+        .map(|role| format!("**{}**", role))
+        .collect::<Vec<_>>();
+
+    {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        if reset {
+            ps.command_permissions.remove(command.name);
+        }
+        else {
+            ps.command_permissions.insert(command.name.to_string(), roles);
+        }
+        ps.save()?;
+    }
+
+    let mut reply = format!("`!{}` may now be used by {}.", command.name, format_allowed_roles(command.name));
+    if !unknown.is_empty() {
+        reply.push_str(&format!("\nThe server has no role named {} yet.", unknown.join(", ")));
+    }
+    println!("{} changed the permissions of !{}", author.name, command.name);
+    send_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
@@ -1,18 +1,25 @@
-use regex::Regex;
-use lazy_static::lazy_static;
+use serde_derive::{Serialize, Deserialize};
 use twilight::{
     http::Client as HttpClient,
     model::{
-        channel::{Message, Reaction, ReactionType},
-        id::{ChannelId, GuildId, MessageId},
+        channel::{Message, Reaction},
+        guild::{Permissions, Role},
+        id::{ChannelId, GuildId, MessageId, UserId},
         user::{User, CurrentUser},
     },
 };
 
-use crate::role::{has_role, remove_role, set_role};
-use crate::roles::*;
+use crate::emoji::{RoleEmoji, fetch_guild_emojis};
+use crate::permissions::{has_permission, permission_denied};
+use crate::recruit::{RECRUIT_EMOJI, RecruitPost, handle_join_request_reaction, handle_recruit_reaction};
+use crate::role::{RoleSource, remove_role, set_exclusive_role};
+use crate::role_cache::find_role;
+use crate::roles::ORGANIZER;
 use crate::state::PersistentState;
-use crate::utils::{Result, send_message};
+use crate::utils::{MAX_MESSAGE_LENGTH, Result, parse_channel, send_long_message, send_message};
+
+/// Discord's limit on the number of different reactions on a message
+const MAX_REACTIONS: usize = 20;
 
 
 pub async fn handle_reaction_add(
@@ -21,6 +28,17 @@ pub async fn handle_reaction_add(
     current_user: &CurrentUser,
 ) -> Result<()> {
     handle_add_role(&http, reaction, &current_user).await?;
+    handle_recruit_reaction(&http, reaction, &current_user).await?;
+    Ok(())
+}
+
+/// Handles reactions in PMs, which are used to answer join requests
+pub async fn handle_pm_reaction_add(
+    reaction: &Reaction,
+    http: HttpClient,
+    current_user: &CurrentUser,
+) -> Result<()> {
+    handle_join_request_reaction(&http, reaction, current_user).await?;
     Ok(())
 }
 
@@ -32,15 +50,136 @@ pub async fn handle_reaction_remove(
     Ok(())
 }
 
-fn emoji_to_role(emoji: &String) -> Option<&str> {
-    if      emoji == "💻" { Some(PROGRAMMER) }
-    else if emoji == "🎨" { Some(ARTIST_2D) }
-    else if emoji == "🗿" { Some(ARTIST_3D) }
-    else if emoji == "🔊" { Some(SOUND_DESIGNER) }
-    else if emoji == "🎵" { Some(MUSICIAN) }
-    else if emoji == "💡" { Some(IDEA_GUY) }
-    else if emoji == "🎲" { Some(BOARD_GAMES) }
-    else { None }
+/// A message that members react to in order to get roles
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReactionRoleMessage {
+    pub channel_id: ChannelId,
+    /// Which role each emoji gives, in the order the reactions are seeded
+    pub bindings: Vec<EmojiBinding>,
+    /// Whether the bot posted the message, and keeps its text in sync with the bindings
+    #[serde(default)]
+    pub managed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EmojiBinding {
+    pub emoji: RoleEmoji,
+    pub role: String,
+}
+
+impl PersistentState {
+    /**
+      Bindings for the emoji of every requestable role, or only of the roles
+      in `category` if it is given
+    */
+    pub fn default_bindings(&self, category: Option<&str>) -> Vec<EmojiBinding> {
+        self.requestable_roles.iter()
+            .filter(|role| category.map(|category| role.category.to_lowercase() == category.to_lowercase()).unwrap_or(true))
+            .filter_map(|role| role.emoji.as_ref().map(|emoji| EmojiBinding {
+                emoji: emoji.clone(),
+                role: role.name.clone(),
+            }))
+            .collect()
+    }
+}
+
+/**
+  Like `default_bindings`, but leaves out custom emoji that the bot can no
+  longer use, such as deleted ones
+*/
+async fn usable_default_bindings(
+    http: &HttpClient,
+    guild: GuildId,
+    category: Option<&str>,
+) -> Vec<EmojiBinding> {
+    let bindings = PersistentState::instance().lock().unwrap().default_bindings(category);
+    let guild_emojis = fetch_guild_emojis(http, guild).await;
+    let mut usable = Vec::new();
+    for binding in bindings {
+        if binding.emoji.is_usable(&guild_emojis) {
+            usable.push(binding);
+        }
+        else {
+            println!("Not binding {} to {}, the emoji can't be used", binding.emoji, binding.role);
+        }
+    }
+    usable
+}
+
+/**
+  Reacts with the emoji of every binding so members can click them,
+  returning the emoji that could not be added
+*/
+async fn seed_reactions(
+    http: &HttpClient,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    bindings: &[EmojiBinding],
+) -> Vec<String> {
+    let mut failed = Vec::new();
+    for binding in bindings {
+        if let Err(e) = http.create_reaction(channel_id, message_id, binding.emoji.reaction_string()).await {
+            println!("Failed to react with {} on message {}: {:?}", binding.emoji, message_id, e);
+            failed.push(binding.emoji.to_string());
+        }
+    }
+    failed
+}
+
+fn too_many_bindings(count: usize) -> String {
+    format!(
+        "That would be {} emoji, but Discord allows only {} different reactions on a message. \
+        Use a role category to split the roles over several messages.", count, MAX_REACTIONS
+    )
+}
+
+/// Permissions that anyone reacting must never be able to give themselves
+fn elevated_permissions() -> Permissions {
+    Permissions::ADMINISTRATOR | Permissions::MANAGE_GUILD | Permissions::MANAGE_ROLES
+        | Permissions::MANAGE_CHANNELS | Permissions::MANAGE_MESSAGES | Permissions::MANAGE_WEBHOOKS
+        | Permissions::KICK_MEMBERS | Permissions::BAN_MEMBERS | Permissions::MENTION_EVERYONE
+}
+
+/**
+  Gives the reason a role may not be bound to an emoji, if there is one.
+  Anyone can react, so only requestable roles without elevated permissions
+  may be handed out that way
+*/
+fn binding_refusal(role: &Role) -> Option<String> {
+    let requestable = PersistentState::instance().lock().unwrap()
+        .find_requestable_role(&role.name)
+        .is_some();
+    if role.name.to_lowercase() == ORGANIZER.to_lowercase() {
+        Some(format!("**{}** can't be given through reactions.", role.name))
+    }
+    else if role.managed {
+        Some(format!("**{}** is managed by an integration and can't be given through reactions.", role.name))
+    }
+    else if role.permissions.intersects(elevated_permissions()) {
+        Some(format!("**{}** has moderator permissions and can't be given through reactions.", role.name))
+    }
+    else if !requestable {
+        Some(format!(
+            "**{}** is not a requestable role. Add it with `!addrole` before binding an emoji to it.", role.name
+        ))
+    }
+    else {
+        None
+    }
+}
+
+/**
+  Gets the role to assign for a reaction, if the reaction was made on a
+  reaction-role message
+*/
+fn reaction_to_role(reaction: &Reaction) -> Option<String> {
+    let ps = PersistentState::instance().lock().unwrap();
+    let message = ps.reaction_messages.get(&reaction.message_id)
+        .filter(|message| message.channel_id == reaction.channel_id)?;
+
+    message.bindings.iter()
+        .find(|binding| binding.emoji.matches(&reaction.emoji))
+        .map(|binding| binding.role.clone())
 }
 
 async fn handle_add_role(
@@ -48,31 +187,20 @@ async fn handle_add_role(
     reaction: &Reaction,
     current_user: &CurrentUser,
 ) -> Result<()> {
-    let mut ps = PersistentState::instance().lock().unwrap();
-    if reaction.channel_id == ps.get_role_assign_channel() &&
-        reaction.message_id == ps.get_role_assign_message() {
-
-        let guild_id = reaction.guild_id.unwrap();
-        let user_id = reaction.user_id;
-
-        if user_id != current_user.id {
-            match &reaction.emoji {
-                ReactionType::Unicode {name} => {
-                    let maybe_role = emoji_to_role(name);
-                    match maybe_role {
-                        Some(role_name) => {
-                            match set_role(http, guild_id, user_id, role_name).await {
-                                Err(e) => println!("Failed setting role from reaction {}: {}", name, e),
-                                _ => {}
-                            }
-                        }
-                        None => {}
+    let guild_id = reaction.guild_id.unwrap();
+    let user_id = reaction.user_id;
+
+    if user_id != current_user.id {
+        if let Some(role_name) = reaction_to_role(reaction) {
+            match set_exclusive_role(http, guild_id, user_id, &role_name, RoleSource::Reaction).await {
+                Err(e) => println!("Failed setting role {} from reaction: {}", role_name, e),
+                Ok((_, replaced)) => {
+                    for other in replaced {
+                        println!("{} left the role {} in favour of {}", user_id, other, role_name);
                     }
                 }
-                _ => {}
             }
         }
-        else {}
     }
     Ok(())
 }
@@ -81,34 +209,44 @@ async fn handle_remove_role(
     http: &HttpClient,
     reaction: &Reaction,
 ) -> Result<()> {
-    let mut ps = PersistentState::instance().lock().unwrap();
-    if reaction.channel_id == ps.get_role_assign_channel() &&
-        reaction.message_id == ps.get_role_assign_message() {
-
-        let guild_id = reaction.guild_id.unwrap();
-        let user_id = reaction.user_id;
-
-        match &reaction.emoji {
-            ReactionType::Unicode {name} => {
-                let maybe_role = emoji_to_role(name);
-                match maybe_role {
-                    Some(role_name) => {
-                        match remove_role(http, guild_id, user_id, role_name).await {
-                            Err(e) => println!("Failed to remove role from reaction {}: {}", name, e),
-                            _ => {}
-                        }
-                    }
-                    None => {}
-                }
-            }
+    let guild_id = reaction.guild_id.unwrap();
+    let user_id = reaction.user_id;
+
+    if let Some(role_name) = reaction_to_role(reaction) {
+        match remove_role(http, guild_id, user_id, &role_name).await {
+            Err(e) => println!("Failed to remove role {} from reaction: {}", role_name, e),
             _ => {}
         }
     }
     Ok(())
 }
 
+/// Removes the user's reactions for the roles from every reaction-role message
+pub async fn remove_role_reactions(
+    http: &HttpClient,
+    user_id: UserId,
+    role_names: &[String],
+) {
+    let reactions = {
+        let ps = PersistentState::instance().lock().unwrap();
+        ps.reaction_messages.iter()
+            .flat_map(|(message_id, message)| {
+                message.bindings.iter()
+                    .filter(|binding| role_names.iter().any(|role| role.to_lowercase() == binding.role.to_lowercase()))
+                    .map(move |binding| (message.channel_id, *message_id, binding.emoji.reaction_string()))
+            })
+            .collect::<Vec<_>>()
+    };
+    for (channel_id, message_id, emoji) in reactions {
+        if let Err(e) = http.delete_reaction(channel_id, message_id, emoji.clone(), user_id).await {
+            println!("Failed to remove reaction {} of {}: {:?}", emoji, user_id, e);
+        }
+    }
+}
+
 pub enum ReactionMessageType {
     RoleAssign,
+    TeamRecruit,
 }
 
 pub async fn handle_set_reaction_message<'a>(
@@ -120,115 +258,140 @@ pub async fn handle_set_reaction_message<'a>(
     msg: &Message,
     msg_type: ReactionMessageType,
 ) -> Result<()> {
-    lazy_static! {
-        static ref CHANNEL_MENTION_REGEX: Regex =
-            Regex::new(r"<#(\d+)>").unwrap();
-    }
     let msg_type_name = match msg_type {
         ReactionMessageType::RoleAssign => "role assignment message",
+        ReactionMessageType::TeamRecruit => "team recruiting post",
+    };
+    let reply_subject = match msg_type {
+        ReactionMessageType::RoleAssign => "Server role assignment message",
+        ReactionMessageType::TeamRecruit => "Your team's recruiting post",
     };
 
     println!("Got set {} request \"{}\"", msg_type_name, &msg.content);
 
-    if has_role(
-        &http,
-        guild,
-        author.id,
-        ORGANIZER,
-    ).await? {
+    let command = match msg_type {
+        ReactionMessageType::RoleAssign => "setroleassign",
+        ReactionMessageType::TeamRecruit => "setrecruit",
+    };
+    // Organizers set up role assignment, team owners recruit for their own team
+    let allowed = match msg_type {
+        ReactionMessageType::RoleAssign => has_permission(&http, guild, author.id, command).await?,
+        ReactionMessageType::TeamRecruit => PersistentState::instance().lock().unwrap().has_created_channel(author.id),
+    };
+    if allowed {
 
         // Parse arguments
-        let command = match msg_type {
-            ReactionMessageType::RoleAssign => "setroleassign",
+        let extra_args = match msg_type {
+            ReactionMessageType::RoleAssign => " [role category]",
+            ReactionMessageType::TeamRecruit => "",
         };
         let arg_guide_msg = format!(
-            "Proper usage: `!{} <mention of channel with the message> <message ID>`", command
+            "Proper usage: `!{} <channel with the message> <message ID>{}`", command, extra_args
         );
         if rest_command.len() < 2 {
             send_message(&http, original_channel, author.id, arg_guide_msg).await?;
         }
         else {
-            match CHANNEL_MENTION_REGEX.captures(rest_command[0]) {
-                Some(channel_ids) => {
-                    if channel_ids.len() != 2 {
-                        send_message(&http, original_channel, author.id,
-                            format!("Invalid channel reference.\n{}", arg_guide_msg)
-                        ).await?;
-                    }
-                    else {
-                        match channel_ids[1].parse::<u64>() {
-                            Ok(channel_id_num) => {
-                                match rest_command[1].parse::<u64>() {
-                                    Ok(messege_id_num) => {
-
-                                        // Fetch specified message
-                                        match http.message(
-                                            ChannelId(channel_id_num),
-                                            MessageId(messege_id_num)
-                                        ).await {
-                                            Ok(response) => {
-                                                let reaction_msg = response.unwrap();
-                                                let mut ps = PersistentState::instance().lock().unwrap();
-                                                let result = match msg_type {
-                                                    ReactionMessageType::RoleAssign => {
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "💻").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "🎨").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "🗿").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "🔊").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "🎵").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "💡").await?;
-                                                        http.create_reaction(reaction_msg.channel_id, reaction_msg.id, "🎲").await?;
-                                                        ps.set_role_assign(reaction_msg.channel_id, reaction_msg.id)
-                                                    }
-                                                };
-
-                                                match result {
-                                                    Ok(_) => {
-                                                        send_message(&http, original_channel, author.id,
-                                                            format!(
-                                                                "Server {} set to the following messege by <@{}> in <#{}>:\n>>> {}",
-                                                                msg_type_name, reaction_msg.author.id,
-                                                                reaction_msg.channel_id, reaction_msg.content
-                                                            )
-                                                        ).await?;
-                                                    }
-                                                    Err(ref e) => {
-                                                        send_message(&http, original_channel, author.id,
-                                                            format!("Could not set server {}. Check the logs for details.", msg_type_name)
-                                                        ).await?;
-                                                        println!("Failed setting {}: {:?}", msg_type_name, e);
-                                                    }
-                                                }
+            match parse_channel(&http, guild, rest_command[0]).await? {
+                Some(channel_id) => {
+                    match rest_command[1].parse::<u64>() {
+                        Ok(messege_id_num) => {
+
+                            // Fetch specified message
+                            match http.message(
+                                channel_id,
+                                MessageId(messege_id_num)
+                            ).await {
+                                Ok(Some(reaction_msg)) => {
+                                    let result = match msg_type {
+                                        ReactionMessageType::RoleAssign => {
+                                            let category = rest_command.get(2).map(|_| rest_command[2..].join(" "));
+                                            let bindings = usable_default_bindings(&http, guild, category.as_deref()).await;
+                                            if bindings.is_empty() {
+                                                send_message(&http, original_channel, author.id,
+                                                    "There are no requestable roles with a usable emoji in that category."
+                                                ).await?;
+                                                return Ok(());
+                                            }
+                                            if bindings.len() > MAX_REACTIONS {
+                                                send_message(&http, original_channel, author.id, too_many_bindings(bindings.len())).await?;
+                                                return Ok(());
                                             }
-                                            Err(_) => {
+                                            seed_reactions(&http, reaction_msg.channel_id, reaction_msg.id, &bindings).await;
+                                            let mut ps = PersistentState::instance().lock().unwrap();
+                                            ps.reaction_messages.insert(reaction_msg.id, ReactionRoleMessage {
+                                                channel_id: reaction_msg.channel_id,
+                                                bindings,
+                                                managed: false,
+                                            });
+                                            ps.save()
+                                        }
+                                        ReactionMessageType::TeamRecruit => {
+                                            if reaction_msg.author.id != author.id {
+                                                send_message(&http, original_channel, author.id,
+                                                    "Only your own posts can be used to recruit for your team."
+                                                ).await?;
+                                                return Ok(());
+                                            }
+                                            if let Err(e) = http.create_reaction(reaction_msg.channel_id, reaction_msg.id, RECRUIT_EMOJI).await {
+                                                println!("Failed to react with {} on message {}: {:?}", RECRUIT_EMOJI, reaction_msg.id, e);
                                                 send_message(&http, original_channel, author.id,
                                                     format!(
-                                                        "No message with ID {} was found in <#{}>",
-                                                        messege_id_num, channel_id_num
+                                                        "I couldn't react with {} on that post, so it was not set as your \
+                                                        team's recruiting post. Check that I can add reactions in <#{}>.",
+                                                        RECRUIT_EMOJI, reaction_msg.channel_id
                                                     )
                                                 ).await?;
-                                                println!("No message with ID {} was found in <#{}>",
-                                                    messege_id_num, channel_id_num
-                                                );
+                                                return Ok(());
                                             }
+                                            let mut ps = PersistentState::instance().lock().unwrap();
+                                            ps.recruit_posts.insert(reaction_msg.id, RecruitPost {
+                                                channel_id: reaction_msg.channel_id,
+                                                owner: author.id,
+                                            });
+                                            ps.save()
+                                        }
+                                    };
+
+                                    match result {
+                                        Ok(_) => {
+                                            send_message(&http, original_channel, author.id,
+                                                format!(
+                                                    "{} set to the following messege by <@{}> in <#{}>:\n>>> {}",
+                                                    reply_subject, reaction_msg.author.id,
+                                                    reaction_msg.channel_id, reaction_msg.content
+                                                )
+                                            ).await?;
+                                        }
+                                        Err(ref e) => {
+                                            send_message(&http, original_channel, author.id,
+                                                format!("Could not set the {}. Check the logs for details.", msg_type_name)
+                                            ).await?;
+                                            println!("Failed setting {}: {:?}", msg_type_name, e);
                                         }
-                                    }
-                                    Err(_) => {
-                                        send_message(&http, original_channel, author.id,
-                                            format!("Message ID must be a number.\n{}", arg_guide_msg)
-                                        ).await?;
                                     }
                                 }
-                            }
-                            Err(_) => {
-                                send_message(&http, original_channel, author.id,
-                                    format!("Invalid channel reference.\n{}", arg_guide_msg)
-                                ).await?;
+                                _ => {
+                                    send_message(&http, original_channel, author.id,
+                                        format!(
+                                            "No message with ID {} was found in <#{}>",
+                                            messege_id_num, channel_id
+                                        )
+                                    ).await?;
+                                    println!("No message with ID {} was found in <#{}>",
+                                        messege_id_num, channel_id
+                                    );
+                                }
                             }
                         }
+                        Err(_) => {
+                            send_message(&http, original_channel, author.id,
+                                format!("Message ID must be a number.\n{}", arg_guide_msg)
+                            ).await?;
+                        }
                     }
                 }
-                _ => {
+                None => {
                     send_message(&http, original_channel, author.id,
                         format!("Invalid channel reference.\n{}", arg_guide_msg)
                     ).await?;
@@ -237,13 +400,410 @@ pub async fn handle_set_reaction_message<'a>(
         }
     }
     else {
+        match msg_type {
+            ReactionMessageType::RoleAssign => {
+                permission_denied(&http, original_channel, author.id, command,
+                    &format!("set the server {}", msg_type_name)
+                ).await?;
+            }
+            ReactionMessageType::TeamRecruit => {
+                send_message(&http, original_channel, author.id,
+                    "You need to create a team with `!createchannels` before you can recruit members."
+                ).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Formats a reaction-role message and its bindings for listing
+fn format_reaction_message(message_id: MessageId, message: &ReactionRoleMessage) -> String {
+    let bindings = message.bindings.iter()
+        .map(|binding| format!("{} **{}**", binding.emoji, binding.role))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("Message {} in <#{}>: {}", message_id, message.channel_id, bindings)
+}
+
+pub async fn handle_list_reaction_messages(
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "rolemessages").await? {
+        permission_denied(&http, original_channel, author.id, "rolemessages", "list the role assignment messages").await?;
+        return Ok(());
+    }
+
+    let lines = PersistentState::instance().lock().unwrap()
+        .reaction_messages.iter()
+        .map(|(message_id, message)| format_reaction_message(*message_id, message))
+        .collect::<Vec<_>>();
+    let reply = if lines.is_empty() {
+        "There are no role assignment messages. Add one with `!setroleassign`.".to_string()
+    }
+    else {
+        format!("Role assignment messages:\n{}", lines.join("\n"))
+    };
+    send_long_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
+
+pub async fn handle_remove_reaction_message<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "removerolemessage").await? {
+        permission_denied(&http, original_channel, author.id, "removerolemessage", "remove role assignment messages").await?;
+        return Ok(());
+    }
+
+    let message_id = match rest_command {
+        [message_id] => message_id.parse::<u64>().ok().map(MessageId),
+        _ => None,
+    };
+    let message_id = match message_id {
+        Some(message_id) => message_id,
+        None => {
+            send_message(&http, original_channel, author.id,
+                "Proper usage: `!removerolemessage <message ID>`"
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let removed = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let removed = ps.reaction_messages.remove(&message_id);
+        ps.save()?;
+        removed
+    };
+    let reply = match removed {
+        // Members keep the roles they already got from the message
+        Some(message) => format!(
+            "Reactions on message {} in <#{}> no longer assign roles.", message_id, message.channel_id
+        ),
+        None => format!("Message {} is not a role assignment message.", message_id),
+    };
+    send_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
+
+/**
+  Picks the reaction-role message a binding command applies to. The message
+  ID may be left out when there is only one reaction-role message
+*/
+fn pick_reaction_message(
+    ps: &PersistentState,
+    message_arg: Option<&str>,
+) -> std::result::Result<(MessageId, ChannelId), String> {
+    match message_arg {
+        Some(arg) => {
+            let message_id = MessageId(arg.parse::<u64>().map_err(|_| "Message ID must be a number.".to_string())?);
+            ps.reaction_messages.get(&message_id)
+                .map(|message| (message_id, message.channel_id))
+                .ok_or_else(|| format!(
+                    "Message {} is not a role assignment message, add it with `!setroleassign` first.", message_id
+                ))
+        }
+        None => {
+            let mut messages = ps.reaction_messages.iter();
+            match (messages.next(), messages.next()) {
+                (Some((message_id, message)), None) => Ok((*message_id, message.channel_id)),
+                (None, _) => Err("There are no role assignment messages, add one with `!setroleassign` first.".to_string()),
+                _ => Err("There are several role assignment messages, give the ID of the one to change. \
+                    `!rolemessages` lists them.".to_string()),
+            }
+        }
+    }
+}
+
+pub async fn handle_bind_emoji<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "bindemoji").await? {
+        permission_denied(&http, original_channel, author.id, "bindemoji", "bind emoji to roles").await?;
+        return Ok(());
+    }
+
+    // The message ID is optional, and is the only argument made of digits
+    let (message_arg, rest) = match rest_command {
+        [first, rest @ ..] if first.chars().all(|c| c.is_ascii_digit()) => (Some(*first), rest),
+        rest => (None, rest),
+    };
+    let (emoji, role_name) = match rest {
+        [emoji, role_name @ ..] if !role_name.is_empty() => (RoleEmoji::parse(emoji), role_name.join(" ")),
+        _ => {
+            send_message(&http, original_channel, author.id,
+                "Proper usage: `!bindemoji [message ID] <emoji> <role name>`"
+            ).await?;
+            return Ok(());
+        }
+    };
+    let emoji = match emoji {
+        Some(emoji) if emoji.is_usable(&fetch_guild_emojis(&http, guild).await) => emoji,
+        _ => {
+            send_message(&http, original_channel, author.id,
+                format!("I can't react with {}. Use a unicode emoji or an emoji of this server.", rest[0])
+            ).await?;
+            return Ok(());
+        }
+    };
+    let role = match find_role(&http, guild, &role_name).await? {
+        Some(role) => role,
+        None => {
+            send_message(&http, original_channel, author.id,
+                format!("The server has no role named **{}**.", role_name)
+            ).await?;
+            return Ok(());
+        }
+    };
+    if let Some(reason) = binding_refusal(&role) {
+        send_message(&http, original_channel, author.id, reason).await?;
+        return Ok(());
+    }
+
+    let picked = pick_reaction_message(&PersistentState::instance().lock().unwrap(), message_arg);
+    let (message_id, channel_id) = match picked {
+        Ok(picked) => picked,
+        Err(reply) => {
+            send_message(&http, original_channel, author.id, reply).await?;
+            return Ok(());
+        }
+    };
+
+    let binding_count = PersistentState::instance().lock().unwrap()
+        .reaction_messages.get(&message_id)
+        .map(|message| message.bindings.iter().filter(|binding| binding.emoji != emoji).count() + 1)
+        .unwrap_or(0);
+    if binding_count > MAX_REACTIONS {
+        send_message(&http, original_channel, author.id, too_many_bindings(binding_count)).await?;
+        return Ok(());
+    }
+    if let Err(e) = http.create_reaction(channel_id, message_id, emoji.reaction_string()).await {
+        println!("Failed to react with {} on message {}: {:?}", emoji, message_id, e);
         send_message(&http, original_channel, author.id,
-            format!(
-                "Since you lack the required role **{}**, you do \
-                not have permission to set the server {}.", ORGANIZER, msg_type_name)
+            format!("I couldn't react with {} on message {}, so it was not bound.", emoji, message_id)
         ).await?;
-        println!("Tried to set {} without required role \"{}\"", msg_type_name, ORGANIZER);
+        return Ok(());
+    }
+    let replaced = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let bindings = &mut ps.reaction_messages.get_mut(&message_id).unwrap().bindings;
+        let replaced = match bindings.iter_mut().find(|binding| binding.emoji == emoji) {
+            Some(binding) => Some(std::mem::replace(&mut binding.role, role.name.clone())),
+            None => {
+                bindings.push(EmojiBinding { emoji: emoji.clone(), role: role.name.clone() });
+                None
+            }
+        };
+        ps.save()?;
+        replaced
+    };
+
+    refresh_role_message(&http, message_id).await;
+    println!("{} bound {} to {} on message {}", author.name, emoji, role.name, message_id);
+    let reply = match replaced {
+        Some(previous) => format!(
+            "Reacting with {} now gives **{}** instead of **{}**.", emoji, role.name, previous
+        ),
+        None => format!("Reacting with {} on message {} now gives **{}**.", emoji, message_id, role.name),
+    };
+    send_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
+
+pub async fn handle_unbind_emoji<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "unbindemoji").await? {
+        permission_denied(&http, original_channel, author.id, "unbindemoji", "unbind emoji from roles").await?;
+        return Ok(());
     }
 
+    let (message_arg, emoji) = match rest_command {
+        [message_id, emoji] => (Some(*message_id), RoleEmoji::parse(emoji)),
+        [emoji] => (None, RoleEmoji::parse(emoji)),
+        _ => (None, None),
+    };
+    let emoji = match emoji {
+        Some(emoji) => emoji,
+        None => {
+            send_message(&http, original_channel, author.id,
+                "Proper usage: `!unbindemoji [message ID] <emoji>`"
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let removed = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        match pick_reaction_message(&ps, message_arg) {
+            Ok((message_id, channel_id)) => {
+                let bindings = &mut ps.reaction_messages.get_mut(&message_id).unwrap().bindings;
+                let position = bindings.iter().position(|binding| binding.emoji == emoji);
+                let removed = position.map(|position| bindings.remove(position));
+                ps.save()?;
+                Ok((message_id, channel_id, removed))
+            }
+            Err(reply) => Err(reply),
+        }
+    };
+
+    let reply = match removed {
+        Ok((message_id, channel_id, Some(binding))) => {
+            // Members keep the role, only the bot's own seed reaction is removed
+            if let Err(e) = http.delete_current_user_reaction(channel_id, message_id, emoji.reaction_string()).await {
+                println!("Failed to remove own reaction {} from message {}: {:?}", emoji, message_id, e);
+            }
+            refresh_role_message(&http, message_id).await;
+            println!("{} unbound {} from {} on message {}", author.name, emoji, binding.role, message_id);
+            format!("Reacting with {} no longer gives **{}**.", emoji, binding.role)
+        }
+        Ok((message_id, _, None)) => format!("{} is not bound to a role on message {}.", emoji, message_id),
+        Err(reply) => reply,
+    };
+    send_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
+
+/// The text of a role assignment message posted by the bot
+fn format_role_message(message: &ReactionRoleMessage) -> String {
+    let ps = PersistentState::instance().lock().unwrap();
+    let roles = message.bindings.iter()
+        .map(|binding| {
+            match ps.find_requestable_role(&binding.role).filter(|role| !role.description.is_empty()) {
+                Some(role) => format!("{} **{}** - {}", binding.emoji, binding.role, role.description),
+                None => format!("{} **{}**", binding.emoji, binding.role),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "**Pick your roles!**\nReact with an emoji below to get its role, \
+        and remove your reaction to leave the role again.\n\n{}",
+        roles
+    )
+}
+
+/// Updates the text of the message if the bot posted it
+pub async fn refresh_role_message(http: &HttpClient, message_id: MessageId) {
+    let message = PersistentState::instance().lock().unwrap()
+        .reaction_messages.get(&message_id)
+        .filter(|message| message.managed)
+        .cloned();
+    if let Some(message) = message {
+        let content = format_role_message(&message);
+        if let Err(e) = http.update_message(message.channel_id, message_id).content(content).await {
+            println!("Failed to update role assignment message {}: {:?}", message_id, e);
+        }
+    }
+}
+
+/// Updates the text of every role assignment message the bot has posted
+pub async fn refresh_role_messages(http: &HttpClient) {
+    let message_ids = PersistentState::instance().lock().unwrap()
+        .reaction_messages.iter()
+        .filter(|(_, message)| message.managed)
+        .map(|(message_id, _)| *message_id)
+        .collect::<Vec<_>>();
+    for message_id in message_ids {
+        refresh_role_message(http, message_id).await;
+    }
+}
+
+pub async fn handle_post_role_message<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "postroleassign").await? {
+        permission_denied(&http, original_channel, author.id, "postroleassign", "post role assignment messages").await?;
+        return Ok(());
+    }
+
+    let channel_id = match rest_command.get(0) {
+        Some(channel) => parse_channel(&http, guild, channel).await?,
+        None => None,
+    };
+    let channel_id = match channel_id {
+        Some(channel_id) => channel_id,
+        None => {
+            send_message(&http, original_channel, author.id,
+                "Proper usage: `!postroleassign <channel> [role category]`"
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let category = rest_command.get(1).map(|_| rest_command[1..].join(" "));
+    let bindings = usable_default_bindings(&http, guild, category.as_deref()).await;
+    if bindings.is_empty() {
+        send_message(&http, original_channel, author.id,
+            "There are no requestable roles with a usable emoji in that category."
+        ).await?;
+        return Ok(());
+    }
+
+    if bindings.len() > MAX_REACTIONS {
+        send_message(&http, original_channel, author.id, too_many_bindings(bindings.len())).await?;
+        return Ok(());
+    }
+
+    let message = ReactionRoleMessage { channel_id, bindings, managed: true };
+    let content = format_role_message(&message);
+    if content.len() > MAX_MESSAGE_LENGTH {
+        send_message(&http, original_channel, author.id,
+            "The role descriptions don't fit in a single message. \
+            Use a role category to split the roles over several messages."
+        ).await?;
+        return Ok(());
+    }
+    let posted = match http.create_message(channel_id).content(content).await {
+        Ok(posted) => posted,
+        Err(e) => {
+            println!("Failed to post role assignment message in {}: {:?}", channel_id, e);
+            send_message(&http, original_channel, author.id,
+                format!("I couldn't post in <#{}>.", channel_id)
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    // Register the message before seeding, so reactions work even if seeding fails
+    let bindings = message.bindings.clone();
+    {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        ps.reaction_messages.insert(posted.id, message);
+        ps.save()?;
+    }
+    let failed = seed_reactions(&http, channel_id, posted.id, &bindings).await;
+
+    println!("{} posted role assignment message {} in {}", author.name, posted.id, channel_id);
+    let mut reply = format!(
+        "Posted a role assignment message in <#{}>. Its ID is {}, and I'll keep it \
+        up to date when emoji are bound or unbound.", channel_id, posted.id
+    );
+    if !failed.is_empty() {
+        reply.push_str(&format!(
+            "\nI couldn't add the reactions {}, members can still add them themselves.", failed.join(" ")
+        ));
+    }
+    send_message(&http, original_channel, author.id, reply).await?;
     Ok(())
 }
@@ -0,0 +1,226 @@
+use serde_derive::{Serialize, Deserialize};
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        channel::{Message, Reaction, ReactionType},
+        id::{ChannelId, GuildId, UserId},
+        user::CurrentUser,
+    },
+};
+
+use crate::channel::{Team, grant_team_access};
+use crate::state::PersistentState;
+use crate::utils::Result;
+
+/// Reacting with this on a recruiting post asks to join the team
+pub const RECRUIT_EMOJI: &str = "✋";
+const ACCEPT_EMOJI: &str = "✅";
+const DECLINE_EMOJI: &str = "❌";
+
+/// A team owner's post that others can react to in order to join the team
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecruitPost {
+    pub channel_id: ChannelId,
+    /// The creator of the team, which is its key in `channel_creators`
+    pub owner: UserId,
+}
+
+/// A request to join a team, sent to the team owner as a PM
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JoinRequest {
+    pub guild_id: GuildId,
+    pub owner: UserId,
+    pub applicant: UserId,
+}
+
+impl PersistentState {
+    /// Adds the user to the team of `owner`, returning the updated team
+    fn add_team_member(&mut self, owner: UserId, user: UserId) -> Result<Option<Team>> {
+        let team = match self.channel_creators.get_mut(&owner) {
+            Some(team) => {
+                team.members.push(user);
+                team.clone()
+            }
+            None => return Ok(None),
+        };
+        self.looking_for_team.remove(&user);
+        self.save()?;
+        Ok(Some(team))
+    }
+}
+
+fn is_emoji(reaction: &Reaction, emoji: &str) -> bool {
+    match &reaction.emoji {
+        ReactionType::Unicode { name } => name == emoji,
+        _ => false,
+    }
+}
+
+/**
+  Sends a PM to the user, logging failures since they may have PMs turned
+  off. Returns the sent message
+*/
+async fn send_pm(http: &HttpClient, user: UserId, content: String) -> Option<Message> {
+    let result = match http.create_private_channel(user).await {
+        Ok(channel) => http.create_message(channel.id).content(content).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(message) => Some(message),
+        Err(e) => {
+            println!("Failed to send PM to {}: {:?}", user, e);
+            None
+        }
+    }
+}
+
+/// Asks the team owner to accept or decline a user who reacted to their recruiting post
+pub async fn handle_recruit_reaction(
+    http: &HttpClient,
+    reaction: &Reaction,
+    current_user: &CurrentUser,
+) -> Result<()> {
+    if reaction.user_id == current_user.id || !is_emoji(reaction, RECRUIT_EMOJI) {
+        return Ok(());
+    }
+    let applicant = reaction.user_id;
+
+    let (owner, game_name) = {
+        let ps = PersistentState::instance().lock().unwrap();
+        let post = match ps.recruit_posts.get(&reaction.message_id) {
+            Some(post) if post.channel_id == reaction.channel_id => post,
+            _ => return Ok(()),
+        };
+        // The team may have been removed since the post was made
+        let team = match ps.channel_creators.get(&post.owner) {
+            Some(team) => team,
+            None => return Ok(()),
+        };
+        let already_asked = ps.join_requests.values()
+            .any(|request| request.owner == post.owner && request.applicant == applicant);
+        if ps.team_of(applicant).is_some() || already_asked {
+            println!("{} asked to join {} but is in a team or has already asked", applicant, team.game_name);
+            return Ok(());
+        }
+        (post.owner, team.game_name.clone())
+    };
+
+    let request_msg = send_pm(http, owner, format!(
+        "<@{}> would like to join your team **{}**. React with {} to accept or {} to decline.",
+        applicant, game_name, ACCEPT_EMOJI, DECLINE_EMOJI
+    )).await;
+    let request_msg = match request_msg {
+        Some(request_msg) => request_msg,
+        None => {
+            send_pm(http, applicant, format!(
+                "Your request to join **{}** could not be delivered, since the team owner doesn't \
+                accept PMs from me. Try asking them directly.", game_name
+            )).await;
+            return Ok(());
+        }
+    };
+
+    // Register the request first, the owner can add the reactions themselves if seeding fails
+    {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        ps.join_requests.insert(request_msg.id, JoinRequest {
+            guild_id: reaction.guild_id.unwrap(),
+            owner,
+            applicant,
+        });
+        ps.save()?;
+    }
+    for emoji in &[ACCEPT_EMOJI, DECLINE_EMOJI] {
+        if let Err(e) = http.create_reaction(request_msg.channel_id, request_msg.id, *emoji).await {
+            println!("Failed to react with {} on join request {}: {:?}", emoji, request_msg.id, e);
+        }
+    }
+    println!("{} asked to join {}", applicant, game_name);
+    Ok(())
+}
+
+/// Accepts or declines a join request when the team owner reacts to it in their PMs
+pub async fn handle_join_request_reaction(
+    http: &HttpClient,
+    reaction: &Reaction,
+    current_user: &CurrentUser,
+) -> Result<()> {
+    if reaction.user_id == current_user.id {
+        return Ok(());
+    }
+    let accepted = if is_emoji(reaction, ACCEPT_EMOJI) {
+        true
+    }
+    else if is_emoji(reaction, DECLINE_EMOJI) {
+        false
+    }
+    else {
+        return Ok(());
+    };
+
+    let request = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let request = ps.join_requests.get(&reaction.message_id)
+            .filter(|request| request.owner == reaction.user_id)
+            .cloned();
+        if request.is_some() {
+            ps.join_requests.remove(&reaction.message_id);
+            ps.save()?;
+        }
+        request
+    };
+    let request = match request {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+
+    if !accepted {
+        println!("{} declined {}", request.owner, request.applicant);
+        send_pm(http, request.owner, format!("You declined <@{}>.", request.applicant)).await;
+        send_pm(http, request.applicant, "Your request to join the team was declined.".to_string()).await;
+        return Ok(());
+    }
+
+    let team = {
+        let ps = PersistentState::instance().lock().unwrap();
+        // The applicant may have joined another team while the request was pending
+        if ps.team_of(request.applicant).is_some() {
+            None
+        }
+        else {
+            ps.channel_creators.get(&request.owner).cloned()
+        }
+    };
+    // Access is granted before the membership is recorded, so a failure leaves nothing half done
+    let team = match team {
+        Some(team) => match grant_team_access(http, &team, request.applicant).await {
+            Ok(_) => PersistentState::instance().lock().unwrap()
+                .add_team_member(request.owner, request.applicant)?,
+            Err(e) => {
+                println!("Failed to give {} access to {}: {:?}", request.applicant, team.game_name, e);
+                send_pm(http, request.owner,
+                    format!("<@{}> could not be given access to your channels. Ask an organizer for help.", request.applicant)
+                ).await;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    match team {
+        Some(team) => {
+            println!("{} joined {}", request.applicant, team.game_name);
+            send_pm(http, request.owner,
+                format!("<@{}> is now a member of **{}**.", request.applicant, team.game_name)
+            ).await;
+            send_pm(http, request.applicant,
+                format!("You are now a member of **{}**! Say hi in <#{}>.", team.game_name, team.text_id)
+            ).await;
+        }
+        None => {
+            send_pm(http, request.owner,
+                format!("<@{}> could not be added, they are already in a team or your team was removed.", request.applicant)
+            ).await;
+        }
+    }
+    Ok(())
+}
@@ -1,58 +1,39 @@
-use std::collections::HashSet;
 use std::fmt::Display;
 
-use lazy_static::lazy_static;
+use serde_derive::{Serialize, Deserialize};
 use twilight::{
     http::Client as HttpClient,
     http::error::Error as DiscordError,
     model::{
-        id::{ChannelId, UserId, GuildId},
+        id::{ChannelId, RoleId, UserId, GuildId},
         user::User,
     },
 };
 
+use crate::emoji::{RoleEmoji, fetch_guild_emojis};
+use crate::expiry::RoleExpiry;
+use crate::permissions::{has_permission, permission_denied};
+use crate::reaction::{refresh_role_messages, remove_role_reactions};
+use crate::role_cache::{RoleCache, find_role, member_roles};
 use crate::roles::*;
-use crate::utils::{Result, send_message};
-
-
-lazy_static! {
-    static ref REQUESTABLE_ROLES : HashSet<String> = {
-        let mut set = HashSet::new();
-        set.insert(PROGRAMMER.to_lowercase());
-        set.insert(ARTIST_2D.to_lowercase());
-        set.insert(ARTIST_3D.to_lowercase());
-        set.insert(SOUND_DESIGNER.to_lowercase());
-        set.insert(MUSICIAN.to_lowercase());
-        set.insert(IDEA_GUY.to_lowercase());
-        set.insert(BOARD_GAMES.to_lowercase());
-        set
-    };
-}
+use crate::state::PersistentState;
+use crate::stats::RoleSignup;
+use crate::utils::{Result, send_message, unix_now};
 
-pub async fn has_role(
-    http: &HttpClient,
-    guild_id: GuildId,
-    user_id: UserId,
-    role_name: impl ToString,
-) -> Result<bool> {
-    let guild_roles = http.roles(guild_id).await?;
-    let user_roles = http.guild_member(guild_id, user_id).await?.unwrap().roles;
-    let role_to_check = role_name.to_string().to_lowercase();
 
-    for role in guild_roles {
-        if role.name.to_lowercase() == role_to_check
-            && user_roles.contains(&role.id)
-        {
-            return Ok(true)
-        }
-    }
-    Ok(false)
+/// The reply to role requests for roles that do not exist
+fn invalid_role_message() -> String {
+    format!(
+        "You need to to specify a valid role.\nAvailable roles are:\n{}",
+        PersistentState::instance().lock().unwrap().format_requestable_roles()
+    )
 }
 
 impl std::error::Error for RoleError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::InvalidName(_)
+                | Self::Ambiguous(_)
                 | Self::AlreadySet(_)
                 | Self::NotSet(_) => None,
             Self::RequestFailed(e) => Some(e)
@@ -60,36 +41,92 @@ impl std::error::Error for RoleError {
     }
 }
 
+/// Records a change of a requestable role in the sign-up statistics
+fn record_role_change(user: UserId, role_name: &str, added: bool) {
+    let mut ps = PersistentState::instance().lock().unwrap();
+    if let Some(role) = ps.find_requestable_role(role_name).map(|role| role.name.clone()) {
+        ps.role_signups.push(RoleSignup { timestamp: unix_now(), user, role, added });
+        if let Err(e) = ps.save() {
+            println!("Failed to save role sign-up statistics: {:?}", e);
+        }
+    }
+}
+
+/// How the bot came to give a user a role
+#[derive(Clone, Copy, PartialEq)]
+pub enum RoleSource {
+    /// Requested with a command such as `!role`
+    Command,
+    /// Reacting on a role assignment message
+    Reaction,
+    /// Given with `!temprole`, and removed again at the unix timestamp
+    Temporary(u64),
+}
+
+/**
+  A role the bot gave because of a reaction. Only these roles are removed
+  when syncing roles with reactions, since other roles never had one
+*/
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ReactionGrant {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    pub role_id: RoleId,
+}
+
+/// Gives the user the role, recording where it came from
 pub async fn set_role(
     http: &HttpClient,
     guild: GuildId,
     user_id: UserId,
     role_name: impl ToString,
+    source: RoleSource,
 ) -> std::result::Result<String, RoleError<>> {
-    let requested_role = role_name.to_string().to_lowercase();
-    let guild_roles = http.roles(guild).await?;
-    let author_roles = http.guild_member(guild, user_id).await?.unwrap().roles;
-
-    for role in guild_roles {
-        if role.name.to_lowercase() == requested_role {
-            return if !author_roles.contains(&role.id) {
-                let request = http.add_guild_member_role(guild, user_id, role.id);
-
-                match request.await {
-                    Err(e) => {
-                        Err(RoleError::RequestFailed(e))
-                    }
-                    _ => {
-                        Ok(role.name)
-                    }
-                }
-            }
-            else {
-                Err(RoleError::AlreadySet(role.name))
+    let role = match find_role(http, guild, &role_name.to_string()).await? {
+        Some(role) => role,
+        None => return Err(RoleError::InvalidName(role_name.to_string())),
+    };
+    if member_roles(http, guild, user_id).await?.contains(&role.id) {
+        // A role the user already has can still be made to expire
+        if let RoleSource::Temporary(expires_at) = source {
+            record_role_expiry(RoleExpiry {
+                guild_id: guild, user_id, role_id: role.id, role_name: role.name.clone(), expires_at
+            });
+        }
+        return Err(RoleError::AlreadySet(role.name));
+    }
+
+    http.add_guild_member_role(guild, user_id, role.id).await?;
+    RoleCache::instance().lock().unwrap().add_member_role(guild, user_id, role.id);
+    record_role_change(user_id, &role.name, true);
+
+    match source {
+        RoleSource::Command => {}
+        RoleSource::Reaction => {
+            let mut ps = PersistentState::instance().lock().unwrap();
+            ps.reaction_grants.push(ReactionGrant { guild_id: guild, user_id, role_id: role.id });
+            if let Err(e) = ps.save() {
+                println!("Failed to save that {} got {} through a reaction: {:?}", user_id, role.name, e);
             }
         }
+        RoleSource::Temporary(expires_at) => record_role_expiry(RoleExpiry {
+            guild_id: guild, user_id, role_id: role.id, role_name: role.name.clone(), expires_at
+        }),
+    }
+    Ok(role.name)
+}
+
+/// Stores when the role should be removed, replacing an earlier expiry of the same role
+fn record_role_expiry(expiry: RoleExpiry) {
+    let mut ps = PersistentState::instance().lock().unwrap();
+    ps.role_expiries.retain(|other| {
+        !(other.guild_id == expiry.guild_id && other.user_id == expiry.user_id && other.role_id == expiry.role_id)
+    });
+    println!("Role {} of {} expires at {}", expiry.role_name, expiry.user_id, expiry.expires_at);
+    ps.role_expiries.push(expiry);
+    if let Err(e) = ps.save() {
+        println!("Failed to save role expiry: {:?}", e);
     }
-    Err(RoleError::InvalidName(role_name.to_string()))
 }
 
 pub async fn remove_role(
@@ -98,30 +135,177 @@ pub async fn remove_role(
     user_id: UserId,
     role_name: impl ToString,
 ) -> std::result::Result<String, RoleError<>> {
-    let requested_role = role_name.to_string().to_lowercase();
-    let guild_roles = http.roles(guild).await?;
-    let author_roles = http.guild_member(guild, user_id).await?.unwrap().roles;
-
-    for role in guild_roles {
-        if role.name.to_lowercase() == requested_role {
-            return if author_roles.contains(&role.id) {
-                let request = http.remove_guild_member_role(guild, user_id, role.id);
-
-                match request.await {
-                    Err(e) => {
-                        Err(RoleError::RequestFailed(e))
-                    }
-                    _ => {
-                        Ok(role.name)
+    let role = match find_role(http, guild, &role_name.to_string()).await? {
+        Some(role) => role,
+        None => return Err(RoleError::InvalidName(role_name.to_string())),
+    };
+    if !member_roles(http, guild, user_id).await?.contains(&role.id) {
+        return Err(RoleError::NotSet(role.name));
+    }
+
+    http.remove_guild_member_role(guild, user_id, role.id).await?;
+    RoleCache::instance().lock().unwrap().remove_member_role(guild, user_id, role.id);
+    record_role_change(user_id, &role.name, false);
+    {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let grant = ReactionGrant { guild_id: guild, user_id, role_id: role.id };
+        if ps.reaction_grants.contains(&grant) {
+            ps.reaction_grants.retain(|other| *other != grant);
+            if let Err(e) = ps.save() {
+                println!("Failed to save that {} lost {}: {:?}", user_id, role.name, e);
+            }
+        }
+    }
+    Ok(role.name)
+}
+
+/**
+  Removes the user's other roles in the exclusive group of `role_name`,
+  along with their reactions on the role assignment message. Returns the
+  names of the removed roles
+*/
+pub async fn clear_exclusive_group(
+    http: &HttpClient,
+    guild: GuildId,
+    user_id: UserId,
+    role_name: &str,
+) -> Vec<String> {
+    let others = PersistentState::instance().lock().unwrap().exclusive_group_members(role_name);
+    let mut removed = Vec::new();
+    for other in others {
+        match remove_role(http, guild, user_id, &other).await {
+            Ok(other) => removed.push(other),
+            Err(RoleError::NotSet(_)) => {}
+            Err(e) => println!("Failed to remove exclusive role {} from {}: {}", other, user_id, e),
+        }
+    }
+    remove_role_reactions(http, user_id, &removed).await;
+    removed
+}
+
+/**
+  Sets a role and removes the other roles of its exclusive group. Returns
+  the name of the set role and the names of the removed ones
+*/
+pub async fn set_exclusive_role(
+    http: &HttpClient,
+    guild: GuildId,
+    user_id: UserId,
+    role_name: impl ToString,
+    source: RoleSource,
+) -> std::result::Result<(String, Vec<String>), RoleError<>> {
+    let role = set_role(http, guild, user_id, role_name, source).await?;
+    let removed = clear_exclusive_group(http, guild, user_id, &role).await;
+    Ok((role, removed))
+}
+
+/// Splits a role request such as `programmer, sound designer; musician` into role names
+pub fn split_role_list(request: &str) -> Vec<String> {
+    request.split(|c| c == ',' || c == ';')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+enum RoleAction {
+    Give,
+    Remove,
+}
+
+/**
+  Gives or removes every role in a comma or semicolon separated list,
+  reporting the outcome per role
+*/
+async fn handle_role_request<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient,
+    action: RoleAction,
+) -> Result<()> {
+    let requested_roles = split_role_list(&rest_command.join(" "));
+    if requested_roles.is_empty() {
+        send_message(&http, original_channel, author.id, invalid_role_message()).await?;
+        return Ok(());
+    }
+
+    let mut results = Vec::new();
+    let mut replaced_roles = Vec::new();
+    for requested_role in &requested_roles {
+        let role_match = PersistentState::instance().lock().unwrap().match_requestable_role(requested_role);
+        let result = match role_match {
+            RoleMatch::NotFound => Err(RoleError::InvalidName(requested_role.clone())),
+            RoleMatch::Ambiguous(candidates) => Err(RoleError::Ambiguous(candidates)),
+            RoleMatch::Found(role_name) => match action {
+                RoleAction::Give => {
+                    let result = set_role(&http, guild, author.id, role_name, RoleSource::Command).await;
+                    if let Ok(role) = &result {
+                        let replaced = clear_exclusive_group(&http, guild, author.id, role).await;
+                        for other in replaced {
+                            println!("{} left the role {} in favour of {}", author.name, other, role);
+                            replaced_roles.push(other);
+                        }
                     }
+                    result
                 }
+                RoleAction::Remove => remove_role(&http, guild, author.id, role_name).await,
             }
-            else {
-                Err(RoleError::NotSet(role.name))
-            }
+        };
+        match (&result, &action) {
+            (Ok(role), RoleAction::Give) => println!("New role {} assigned to {}", role, author.name),
+            (Ok(role), RoleAction::Remove) => println!("{} left the role {}", author.name, role),
+            (Err(e), RoleAction::Give) => println!("Couldn't assign role to {}: {}", author.name, e),
+            (Err(e), RoleAction::Remove) => println!("Couldn't strip {} of role: {}", author.name, e),
         }
+        results.push(result);
     }
-    Err(RoleError::InvalidName(role_name.to_string()))
+
+    let any_invalid = results.iter().any(|result| match result {
+        Err(RoleError::InvalidName(_)) => true,
+        _ => false,
+    });
+    let reply = if let [result] = results.as_slice() {
+        match (result, &action) {
+            (Err(RoleError::InvalidName(_)), _) => invalid_role_message(),
+            (Err(e @ RoleError::Ambiguous(_)), _) => format!("{}", e),
+            (Ok(role), RoleAction::Give) => format!("You have been assigned the role **{}**.", role),
+            (Ok(role), RoleAction::Remove) => format!("You have been stripped of the role **{}**.", role),
+            (Err(e), RoleAction::Give) => format!("Couldn't assign role to you: {}", e),
+            (Err(e), RoleAction::Remove) => format!("Couldn't strip you of role: {}", e),
+        }
+    }
+    else {
+        let lines = results.iter()
+            .map(|result| match (result, &action) {
+                (Ok(role), RoleAction::Give) => format!("✅ Assigned **{}**", role),
+                (Ok(role), RoleAction::Remove) => format!("✅ Stripped **{}**", role),
+                (Err(e), _) => format!("❌ {}", e),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        if any_invalid {
+            format!("{}\n\n{}", lines, invalid_role_message())
+        }
+        else {
+            lines
+        }
+    };
+
+    let reply = if replaced_roles.is_empty() {
+        reply
+    }
+    else {
+        let replaced = replaced_roles.iter()
+            .map(|role| format!("**{}**", role))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{}\nThis replaced your previous role {}.", reply, replaced)
+    };
+
+    send_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
 }
 
 pub async fn handle_give_role<'a>(
@@ -131,64 +315,324 @@ pub async fn handle_give_role<'a>(
     author: &User,
     http: HttpClient
 ) -> Result<()> {
-    let mut message = "You need to to specify a valid role.\nAvailable roles are:```\nProgrammer\n2D Artist\n3D Artist\nSound Designer\nMusician\nIdea Guy\nBoard Games```".to_string();
+    handle_role_request(rest_command, original_channel, guild, author, http, RoleAction::Give).await
+}
+
+pub async fn handle_remove_role<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    handle_role_request(rest_command, original_channel, guild, author, http, RoleAction::Remove).await
+}
 
-    let reply : String = if rest_command.len() == 0 {
-        message.into()
+pub async fn handle_add_requestable_role<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "addrole").await? {
+        permission_denied(&http, original_channel, author.id, "addrole", "add requestable roles").await?;
+        return Ok(());
     }
-    else {
-        let requested_role = rest_command.join(" ");
-        if REQUESTABLE_ROLES.contains(&requested_role.to_lowercase()) {
-            match set_role(&http, guild, author.id, &requested_role).await {
-                Err(e) => {
-                    message = format!("Couldn't assign role to you: {}", e);
-                    println!("Couldn't assign role to {}: {}", author.name, e);
-                }
-                Ok(role) => {
-                    message = format!("You have been assigned the role **{}**.", role);
-                    println!("New role {} assigned to {}", role, author.name);
+
+    let args = rest_command.join(" ");
+    let fields = args.split('|').map(str::trim).collect::<Vec<_>>();
+    let entry = match fields.as_slice() {
+        [name, emoji, category, description @ ..] if !name.is_empty() && !category.is_empty() => {
+            let emoji = if emoji.is_empty() || *emoji == "-" {
+                None
+            }
+            else {
+                match RoleEmoji::parse(emoji) {
+                    Some(emoji) if emoji.is_usable(&fetch_guild_emojis(&http, guild).await) => Some(emoji),
+                    _ => {
+                        send_message(&http, original_channel, author.id,
+                            format!("I can't react with {}. Use a unicode emoji or an emoji of this server.", emoji)
+                        ).await?;
+                        return Ok(());
+                    }
                 }
+            };
+            // Re-adding a role keeps the settings made with other commands
+            let existing = PersistentState::instance().lock().unwrap().find_requestable_role(name).cloned();
+            RoleEntry {
+                name: name.to_string(),
+                description: description.join("|"),
+                emoji,
+                category: category.to_string(),
+                color: existing.as_ref().and_then(|role| role.color),
+                mentionable: existing.as_ref().map(|role| role.mentionable).unwrap_or(true),
+                aliases: existing.as_ref().map(|role| role.aliases.clone()).unwrap_or_default(),
+                exclusive_group: existing.and_then(|role| role.exclusive_group),
             }
         }
-        message.into()
+        _ => {
+            send_message(&http, original_channel, author.id,
+                "Proper usage: `!addrole <role name> | <emoji or -> | <category> | <description>`"
+            ).await?;
+            return Ok(());
+        }
     };
 
+    let exists_in_guild = find_role(&http, guild, &entry.name).await?.is_some();
+
+    let conflict = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let emoji_taken = entry.emoji.as_ref()
+            .and_then(|emoji| ps.role_for_emoji(emoji))
+            .filter(|role| role.name.to_lowercase() != entry.name.to_lowercase())
+            .map(|role| role.name.clone());
+        match emoji_taken {
+            Some(role) => Some(role),
+            None => {
+                ps.requestable_roles.retain(|role| role.name.to_lowercase() != entry.name.to_lowercase());
+                ps.requestable_roles.push(entry.clone());
+                ps.save()?;
+                None
+            }
+        }
+    };
+
+    if conflict.is_none() {
+        // Role assignment messages posted by the bot show the role descriptions
+        refresh_role_messages(&http).await;
+    }
+
+    let reply = match conflict {
+        Some(role) => format!(
+            "The emoji {} is already used by **{}**.", entry.emoji.map(|emoji| emoji.to_string()).unwrap_or_default(), role
+        ),
+        None => {
+            println!("{} made {} requestable", author.name, entry.name);
+            let warning = if exists_in_guild {
+                ""
+            }
+            else {
+                "\nThe server has no role with that name yet, use `!setup` to create it."
+            };
+            format!("**{}** can now be requested.{}", entry.name, warning)
+        }
+    };
     send_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
+
+pub async fn handle_delete_requestable_role<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "delrole").await? {
+        permission_denied(&http, original_channel, author.id, "delrole", "remove requestable roles").await?;
+        return Ok(());
+    }
 
+    let name = rest_command.join(" ");
+    let (removed, unbound) = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let removed = ps.find_requestable_role(&name).map(|role| role.name.clone());
+        // Emoji bound to the role on role assignment messages would keep giving it out
+        let mut unbound = Vec::new();
+        if let Some(removed) = &removed {
+            ps.requestable_roles.retain(|role| &role.name != removed);
+            for (message_id, message) in ps.reaction_messages.iter_mut() {
+                let channel_id = message.channel_id;
+                message.bindings.retain(|binding| {
+                    let bound = binding.role.to_lowercase() == removed.to_lowercase();
+                    if bound {
+                        unbound.push((channel_id, *message_id, binding.emoji.clone()));
+                    }
+                    !bound
+                });
+            }
+            ps.save()?;
+        }
+        (removed, unbound)
+    };
+    if removed.is_some() {
+        // Members keep the role, only the bot's own seed reactions are removed
+        for (channel_id, message_id, emoji) in &unbound {
+            if let Err(e) = http.delete_current_user_reaction(*channel_id, *message_id, emoji.reaction_string()).await {
+                println!("Failed to remove own reaction {} from message {}: {:?}", emoji, message_id, e);
+            }
+        }
+        refresh_role_messages(&http).await;
+    }
+
+    let reply = match removed {
+        Some(role) => {
+            println!("{} made {} no longer requestable", author.name, role);
+            let unbound_note = if unbound.is_empty() {
+                String::new()
+            }
+            else {
+                let emoji = unbound.iter()
+                    .map(|(_, message_id, emoji)| format!("{} on message {}", emoji, message_id))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("\nReacting no longer gives it: unbound {}.", emoji)
+            };
+            format!(
+                "**{}** can no longer be requested. \
+                The role itself is left on the server and its members.{}", role, unbound_note
+            )
+        }
+        None => format!("**{}** is not a requestable role.", name),
+    };
+    send_message(&http, original_channel, author.id, reply).await?;
     Ok(())
 }
 
-pub async fn handle_remove_role<'a>(
+pub async fn handle_set_role_group<'a>(
     rest_command: &[&'a str],
     original_channel: ChannelId,
     guild: GuildId,
     author: &User,
     http: HttpClient
 ) -> Result<()> {
-    let mut message = "You need to to specify a valid role.\nAvailable roles are:```\nProgrammer\n2D Artist\n3D Artist\nSound Designer\nMusician\nIdea Guy\nBoard Games```".to_string();
+    if !has_permission(&http, guild, author.id, "rolegroup").await? {
+        permission_denied(&http, original_channel, author.id, "rolegroup", "group roles").await?;
+        return Ok(());
+    }
 
-    let reply : String = if rest_command.len() == 0 {
-        message.into()
+    let args = rest_command.join(" ");
+    let fields = args.split('|').map(str::trim).collect::<Vec<_>>();
+    let reply = match fields.as_slice() {
+        [name, group] if !name.is_empty() && !group.is_empty() => {
+            let mut ps = PersistentState::instance().lock().unwrap();
+            let group = if group.to_lowercase() == "none" { None } else { Some(group.to_string()) };
+            let name = name.to_lowercase();
+            match ps.requestable_roles.iter_mut().find(|role| role.name.to_lowercase() == name) {
+                Some(role) => {
+                    role.exclusive_group = group.clone();
+                    let role_name = role.name.clone();
+                    ps.save()?;
+                    match group {
+                        Some(group) => format!(
+                            "**{}** is now in the exclusive group **{}**. \
+                            Members can hold only one role of that group.", role_name, group
+                        ),
+                        None => format!("**{}** is no longer in an exclusive group.", role_name),
+                    }
+                }
+                None => format!("**{}** is not a requestable role.", name),
+            }
+        }
+        _ => "Proper usage: `!rolegroup <role name> | <group name or none>`".to_string(),
+    };
+    send_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
+
+/// Parses a role color such as `#3498DB`, where `-` means no color
+fn parse_color(arg: &str) -> Option<Option<u64>> {
+    if arg == "-" {
+        Some(None)
     }
     else {
-        let requested_role = rest_command.join(" ");
-        if REQUESTABLE_ROLES.contains(&requested_role.to_lowercase()) {
-            match remove_role(&http, guild, author.id, &requested_role).await {
-                Err(e) => {
-                    message = format!("Couldn't strip you of role: {}", e);
-                    println!("Couldn't strip {} of role: {}", author.name, e);
-                }
-                Ok(role) => {
-                    message = format!("You have been stripped of the role **{}**.", role);
-                    println!("{} left the role {}", author.name, role);
-                }
+        u64::from_str_radix(arg.trim_start_matches('#'), 16).ok()
+            .filter(|color| *color <= 0xFFFFFF)
+            .map(Some)
+    }
+}
+
+pub async fn handle_set_role_style<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "setrolestyle").await? {
+        permission_denied(&http, original_channel, author.id, "setrolestyle", "change role styles").await?;
+        return Ok(());
+    }
+
+    let args = rest_command.join(" ");
+    let fields = args.split('|').map(str::trim).collect::<Vec<_>>();
+    let style = match fields.as_slice() {
+        [name, color, mentionable] if !name.is_empty() => {
+            let mentionable = match mentionable.to_lowercase().as_str() {
+                "mentionable" => Some(true),
+                "unmentionable" => Some(false),
+                _ => None,
+            };
+            match (parse_color(color), mentionable) {
+                (Some(color), Some(mentionable)) => Some((name.to_string(), color, mentionable)),
+                _ => None,
             }
         }
-        message.into()
+        _ => None,
+    };
+    let (name, color, mentionable) = match style {
+        Some(style) => style,
+        None => {
+            send_message(&http, original_channel, author.id,
+                "Proper usage: `!setrolestyle <role name> | <color or -> | <mentionable|unmentionable>`, \
+                for example `!setrolestyle Programmer | #3498DB | mentionable`"
+            ).await?;
+            return Ok(());
+        }
     };
 
-    send_message(&http, original_channel, author.id, reply).await?;
+    let role_name = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let lowercase_name = name.to_lowercase();
+        let role_name = match ps.requestable_roles.iter_mut().find(|role| role.name.to_lowercase() == lowercase_name) {
+            Some(role) => {
+                role.color = color;
+                role.mentionable = mentionable;
+                Some(role.name.clone())
+            }
+            None => None,
+        };
+        if role_name.is_some() {
+            ps.save()?;
+        }
+        role_name
+    };
+    let role_name = match role_name {
+        Some(role_name) => role_name,
+        None => {
+            send_message(&http, original_channel, author.id,
+                format!("**{}** is not a requestable role.", name)
+            ).await?;
+            return Ok(());
+        }
+    };
 
+    // Roles that already exist on the server are updated right away, others
+    // get the style when `!setup` creates them
+    let applied = match find_role(&http, guild, &role_name).await? {
+        Some(role) => {
+            let mut request = http.update_role(guild, role.id).mentionable(mentionable);
+            if let Some(color) = color {
+                request = request.color(color);
+            }
+            match request.await {
+                Ok(_) => " and applied to the server role".to_string(),
+                Err(e) => {
+                    println!("Failed to update the style of role {}: {:?}", role_name, e);
+                    ", but updating the server role failed".to_string()
+                }
+            }
+        }
+        None => String::new(),
+    };
+    println!("{} changed the style of {}", author.name, role_name);
+    send_message(&http, original_channel, author.id,
+        format!(
+            "The style of **{}** has been saved{}. It is {}.",
+            role_name, applied, if mentionable { "mentionable" } else { "not mentionable" }
+        )
+    ).await?;
     Ok(())
 }
 
@@ -196,6 +640,8 @@ pub async fn handle_remove_role<'a>(
 pub enum RoleError {
     RequestFailed(DiscordError),
     InvalidName(String),
+    /// The requested name matched several roles equally well
+    Ambiguous(Vec<String>),
     AlreadySet(String),
     NotSet(String),
 }
@@ -213,6 +659,14 @@ impl Display for RoleError {
                 format!("Discord error: {}", e),
             Self::InvalidName(invalid_name) =>
                 format!("Invalid role name \"{}\"", invalid_name),
+            Self::Ambiguous(candidates) => {
+                let (last, rest) = candidates.split_last().unwrap();
+                let rest = rest.iter()
+                    .map(|candidate| format!("**{}**", candidate))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Did you mean {} or **{}**?", rest, last)
+            }
             Self::AlreadySet(role) =>
                 format!("Role **{}** already set", role),
             Self::NotSet(role) =>
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use twilight::{
+    gateway::shard::Event,
+    http::Client as HttpClient,
+    http::error::Error as DiscordError,
+    model::{
+        guild::Role,
+        id::{GuildId, RoleId, UserId},
+    },
+};
+
+/**
+  Caches the roles of guilds and of their members, so that checking and
+  changing roles doesn't need HTTP requests every time.
+
+  Entries are fetched on first use and then kept up to date from gateway
+  events. Roles are looked up by ID, names are only compared to resolve
+  role names typed by users
+*/
+#[derive(Default)]
+pub struct RoleCache {
+    guild_roles: HashMap<GuildId, HashMap<RoleId, Role>>,
+    member_roles: HashMap<(GuildId, UserId), Vec<RoleId>>,
+}
+
+impl RoleCache {
+    /// Return the global instance of the cache
+    pub fn instance() -> &'static Mutex<Self> {
+        lazy_static! {
+            static ref INSTANCE: Mutex<RoleCache> = Mutex::new(RoleCache::default());
+        }
+        &INSTANCE
+    }
+
+    /// Applies the role and member changes of a gateway event
+    pub fn update(&mut self, event: &Event) {
+        match event {
+            Event::GuildCreate(guild) => {
+                self.guild_roles.insert(guild.id, guild.roles.clone());
+                // Member updates may have been missed while disconnected
+                self.member_roles.retain(|(guild_id, _), _| *guild_id != guild.id);
+            }
+            Event::RoleCreate(create) => {
+                self.insert_role(create.guild_id, create.role.clone());
+            }
+            Event::RoleUpdate(update) => {
+                self.insert_role(update.guild_id, update.role.clone());
+            }
+            Event::RoleDelete(delete) => {
+                if let Some(roles) = self.guild_roles.get_mut(&delete.guild_id) {
+                    roles.remove(&delete.role_id);
+                }
+                for ((guild_id, _), roles) in self.member_roles.iter_mut() {
+                    if *guild_id == delete.guild_id {
+                        roles.retain(|role| *role != delete.role_id);
+                    }
+                }
+            }
+            Event::MemberAdd(member) => {
+                self.member_roles.insert((member.guild_id, member.user.id), member.roles.clone());
+            }
+            Event::MemberUpdate(update) => {
+                self.member_roles.insert((update.guild_id, update.user.id), update.roles.clone());
+            }
+            Event::MemberRemove(remove) => {
+                self.member_roles.remove(&(remove.guild_id, remove.user.id));
+            }
+            _ => {}
+        }
+    }
+
+    fn insert_role(&mut self, guild_id: GuildId, role: Role) {
+        // Roles of guilds that haven't been fetched yet are fetched in full on first use
+        if let Some(roles) = self.guild_roles.get_mut(&guild_id) {
+            roles.insert(role.id, role);
+        }
+    }
+
+    /// Records a role given to a member by the bot, without waiting for the gateway event
+    pub fn add_member_role(&mut self, guild_id: GuildId, user_id: UserId, role_id: RoleId) {
+        if let Some(roles) = self.member_roles.get_mut(&(guild_id, user_id)) {
+            if !roles.contains(&role_id) {
+                roles.push(role_id);
+            }
+        }
+    }
+
+    /// Records a role removed from a member by the bot, without waiting for the gateway event
+    pub fn remove_member_role(&mut self, guild_id: GuildId, user_id: UserId, role_id: RoleId) {
+        if let Some(roles) = self.member_roles.get_mut(&(guild_id, user_id)) {
+            roles.retain(|role| *role != role_id);
+        }
+    }
+}
+
+/// The roles of the guild, fetched if they aren't cached yet
+pub async fn guild_roles(http: &HttpClient, guild_id: GuildId) -> Result<Vec<Role>, DiscordError> {
+    if let Some(roles) = RoleCache::instance().lock().unwrap().guild_roles.get(&guild_id) {
+        return Ok(roles.values().cloned().collect());
+    }
+    let roles = http.roles(guild_id).await?;
+    RoleCache::instance().lock().unwrap().guild_roles.insert(
+        guild_id,
+        roles.iter().map(|role| (role.id, role.clone())).collect(),
+    );
+    Ok(roles)
+}
+
+/// The IDs of the member's roles, fetched if they aren't cached yet
+pub async fn member_roles(http: &HttpClient, guild_id: GuildId, user_id: UserId) -> Result<Vec<RoleId>, DiscordError> {
+    if let Some(roles) = RoleCache::instance().lock().unwrap().member_roles.get(&(guild_id, user_id)) {
+        return Ok(roles.clone());
+    }
+    match http.guild_member(guild_id, user_id).await? {
+        Some(member) => {
+            RoleCache::instance().lock().unwrap().member_roles.insert((guild_id, user_id), member.roles.clone());
+            Ok(member.roles)
+        }
+        // Users who are not members have no roles
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Finds the guild role with the name, ignoring case
+pub async fn find_role(http: &HttpClient, guild_id: GuildId, name: &str) -> Result<Option<Role>, DiscordError> {
+    let name = name.to_lowercase();
+    Ok(guild_roles(http, guild_id).await?
+        .into_iter()
+        .find(|role| role.name.to_lowercase() == name))
+}
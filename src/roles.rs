@@ -1,3 +1,8 @@
+use serde_derive::{Serialize, Deserialize};
+
+use crate::emoji::RoleEmoji;
+use crate::state::PersistentState;
+
 pub const ORGANIZER:        &'static str = "Organizer";
 pub const PROGRAMMER:       &'static str = "Programmer";
 pub const ARTIST_2D:        &'static str = "2D Artist";
@@ -6,3 +11,291 @@ pub const SOUND_DESIGNER:   &'static str = "Sound Designer";
 pub const MUSICIAN:         &'static str = "Musician";
 pub const IDEA_GUY:         &'static str = "Idea Guy";
 pub const BOARD_GAMES:      &'static str = "Board Games";
+
+const SKILLS_CATEGORY:      &'static str = "Skills";
+
+/// A role that users may request for themselves
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoleEntry {
+    pub name: String,
+    pub description: String,
+    /// Emoji used to request the role on the role assignment message
+    pub emoji: Option<RoleEmoji>,
+    pub category: String,
+    /// Color used when the role is created on a server that lacks it
+    #[serde(default)]
+    pub color: Option<u64>,
+    /// Whether the role can be mentioned by everyone when it is created
+    #[serde(default)]
+    pub mentionable: bool,
+    /// Other names the role can be requested by, such as `sfx` for Sound Designer
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Members may hold at most one role of each exclusive group
+    #[serde(default)]
+    pub exclusive_group: Option<String>,
+}
+
+impl RoleEntry {
+    fn new(name: &str, description: &str, emoji: &str, category: &str, color: u64, aliases: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            emoji: Some(RoleEmoji::Unicode(emoji.to_string())),
+            category: category.to_string(),
+            color: Some(color),
+            mentionable: true,
+            aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+            exclusive_group: None,
+        }
+    }
+}
+
+/// How the organizer role is created on a server that lacks it
+pub fn organizer_role() -> RoleEntry {
+    RoleEntry {
+        name: ORGANIZER.to_string(),
+        description: "Runs the jam".to_string(),
+        emoji: None,
+        category: String::new(),
+        color: Some(0xE91E63),
+        // Pinging every organizer is left to those who already are one
+        mentionable: false,
+        aliases: Vec::new(),
+        exclusive_group: None,
+    }
+}
+
+/// The roles available before organizers have made any changes
+pub fn default_roles() -> Vec<RoleEntry> {
+    vec![
+        RoleEntry::new(PROGRAMMER, "Writes the code", "💻", SKILLS_CATEGORY, 0x3498DB,
+            &["code", "coder", "dev", "developer", "programming"]),
+        RoleEntry::new(ARTIST_2D, "Draws sprites, textures and UI", "🎨", SKILLS_CATEGORY, 0xE67E22,
+            &["2d", "2d art", "pixel art", "drawing"]),
+        RoleEntry::new(ARTIST_3D, "Models, rigs and animates", "🗿", SKILLS_CATEGORY, 0x9B59B6,
+            &["3d", "3d art", "modeling", "modelling"]),
+        RoleEntry::new(SOUND_DESIGNER, "Makes sound effects", "🔊", SKILLS_CATEGORY, 0x1ABC9C,
+            &["sfx", "sound", "audio"]),
+        RoleEntry::new(MUSICIAN, "Composes music", "🎵", SKILLS_CATEGORY, 0x2ECC71,
+            &["music", "composer"]),
+        RoleEntry::new(IDEA_GUY, "Comes up with ideas", "💡", SKILLS_CATEGORY, 0xF1C40F,
+            &["ideas", "design", "designer"]),
+        RoleEntry::new(BOARD_GAMES, "Makes board games", "🎲", SKILLS_CATEGORY, 0xA84300,
+            &["board game", "boardgames", "tabletop"]),
+    ]
+}
+
+/// The outcome of matching user input against the requestable roles
+#[derive(Debug, PartialEq)]
+pub enum RoleMatch {
+    /// A single role matched confidently
+    Found(String),
+    /// Several roles matched equally well
+    Ambiguous(Vec<String>),
+    NotFound,
+}
+
+impl RoleMatch {
+    fn from_candidates(mut candidates: Vec<String>) -> Option<Self> {
+        candidates.dedup();
+        match candidates.len() {
+            0 => None,
+            1 => Some(Self::Found(candidates.remove(0))),
+            _ => Some(Self::Ambiguous(candidates)),
+        }
+    }
+}
+
+/// The number of single character insertions, deletions and substitutions needed to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/**
+  Matches user input against role names and aliases. Tries, in order, exact
+  names, exact aliases, prefixes of names, words or aliases, and finally
+  names or aliases within a small edit distance
+*/
+fn match_role(roles: &[RoleEntry], input: &str) -> RoleMatch {
+    let input = input.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    if input.is_empty() {
+        return RoleMatch::NotFound;
+    }
+    let names_of = |predicate: &dyn Fn(&RoleEntry) -> bool| {
+        roles.iter().filter(|role| predicate(role)).map(|role| role.name.clone()).collect::<Vec<_>>()
+    };
+    let lowercase = |s: &String| s.to_lowercase();
+
+    let exact = names_of(&|role| lowercase(&role.name) == input);
+    let alias = names_of(&|role| role.aliases.iter().map(lowercase).any(|alias| alias == input));
+    let prefix = names_of(&|role| {
+        let name = lowercase(&role.name);
+        input.len() >= 2 && (
+            name.starts_with(&input)
+                || name.split_whitespace().any(|word| word.starts_with(&input))
+                || role.aliases.iter().map(lowercase).any(|alias| alias.starts_with(&input))
+        )
+    });
+    if let Some(found) = RoleMatch::from_candidates(exact)
+        .or_else(|| RoleMatch::from_candidates(alias))
+        .or_else(|| RoleMatch::from_candidates(prefix))
+    {
+        return found;
+    }
+
+    // Allow roughly one typo for every four characters
+    let max_distance = (input.chars().count() / 4).max(1);
+    let distances = roles.iter()
+        .map(|role| {
+            let distance = std::iter::once(&role.name)
+                .chain(role.aliases.iter())
+                .map(|name| edit_distance(&input, &lowercase(name)))
+                .min()
+                .unwrap();
+            (role.name.clone(), distance)
+        })
+        .filter(|(_, distance)| *distance <= max_distance)
+        .collect::<Vec<_>>();
+    let best = distances.iter().map(|(_, distance)| *distance).min();
+    let closest = distances.iter()
+        .filter(|(_, distance)| Some(*distance) == best)
+        .map(|(name, _)| name.clone())
+        .collect();
+    RoleMatch::from_candidates(closest).unwrap_or(RoleMatch::NotFound)
+}
+
+impl PersistentState {
+    /// Matches user input against the requestable roles, allowing aliases and typos
+    pub fn match_requestable_role(&self, input: &str) -> RoleMatch {
+        match_role(&self.requestable_roles, input)
+    }
+
+    /// Finds a requestable role by name, ignoring case
+    pub fn find_requestable_role(&self, name: &str) -> Option<&RoleEntry> {
+        let name = name.to_lowercase();
+        self.requestable_roles.iter().find(|role| role.name.to_lowercase() == name)
+    }
+
+    /// Gets the other roles in the exclusive group of the role, if it has one
+    pub fn exclusive_group_members(&self, role_name: &str) -> Vec<String> {
+        let group = match self.find_requestable_role(role_name) {
+            Some(role) => role.exclusive_group.clone(),
+            None => None,
+        };
+        match group {
+            Some(group) => self.requestable_roles.iter()
+                .filter(|role| role.exclusive_group.as_ref() == Some(&group))
+                .filter(|role| role.name.to_lowercase() != role_name.to_lowercase())
+                .map(|role| role.name.clone())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Finds the requestable role that is requested with the emoji
+    pub fn role_for_emoji(&self, emoji: &RoleEmoji) -> Option<&RoleEntry> {
+        self.requestable_roles.iter().find(|role| role.emoji.as_ref() == Some(emoji))
+    }
+
+    /**
+      Formats all requestable roles, grouped by category, as a list to
+      show to users
+    */
+    pub fn format_requestable_roles(&self) -> String {
+        let mut categories: Vec<&str> = Vec::new();
+        for role in &self.requestable_roles {
+            if !categories.contains(&role.category.as_str()) {
+                categories.push(&role.category);
+            }
+        }
+        categories.iter()
+            .map(|category| {
+                let roles = self.requestable_roles.iter()
+                    .filter(|role| role.category == *category)
+                    .map(|role| {
+                        let emoji = role.emoji.as_ref().map(|emoji| format!("{} ", emoji)).unwrap_or_default();
+                        let group = role.exclusive_group.as_ref()
+                            .map(|group| format!(" *(one {} role only)*", group))
+                            .unwrap_or_default();
+                        if role.description.is_empty() {
+                            format!("{}**{}**{}", emoji, role.name, group)
+                        }
+                        else {
+                            format!("{}**{}**{} - {}", emoji, role.name, group, role.description)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("__{}__\n{}", category, roles)
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(name: &str) -> RoleMatch {
+        RoleMatch::Found(name.to_string())
+    }
+
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(edit_distance("programmer", "programmer"), 0);
+        assert_eq!(edit_distance("programer", "programmer"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "sfx"), 3);
+        assert_eq!(edit_distance("sfx", ""), 3);
+    }
+
+    #[test]
+    fn exact_names_ignore_case_and_whitespace() {
+        let roles = default_roles();
+        assert_eq!(match_role(&roles, "PROGRAMMER"), found(PROGRAMMER));
+        assert_eq!(match_role(&roles, "  sound   designer "), found(SOUND_DESIGNER));
+    }
+
+    #[test]
+    fn typos_are_forgiven() {
+        let roles = default_roles();
+        assert_eq!(match_role(&roles, "programer"), found(PROGRAMMER));
+        assert_eq!(match_role(&roles, "musican"), found(MUSICIAN));
+    }
+
+    #[test]
+    fn aliases_match() {
+        let roles = default_roles();
+        assert_eq!(match_role(&roles, "3d"), found(ARTIST_3D));
+        assert_eq!(match_role(&roles, "sfx"), found(SOUND_DESIGNER));
+        assert_eq!(match_role(&roles, "music"), found(MUSICIAN));
+    }
+
+    #[test]
+    fn shared_words_are_ambiguous() {
+        let roles = default_roles();
+        assert_eq!(
+            match_role(&roles, "artist"),
+            RoleMatch::Ambiguous(vec![ARTIST_2D.to_string(), ARTIST_3D.to_string()])
+        );
+    }
+
+    #[test]
+    fn unknown_roles_are_not_found() {
+        let roles = default_roles();
+        assert_eq!(match_role(&roles, "astronaut"), RoleMatch::NotFound);
+        assert_eq!(match_role(&roles, ""), RoleMatch::NotFound);
+    }
+}
@@ -0,0 +1,100 @@
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        id::{ChannelId, GuildId},
+        user::User,
+    },
+};
+
+use crate::permissions::{has_permission, permission_denied};
+use crate::role_cache::guild_roles;
+use crate::roles::organizer_role;
+use crate::state::PersistentState;
+use crate::utils::{Result, send_alert, send_message};
+
+/// The outcome of making sure every registered role exists in a guild
+pub struct ProvisionReport {
+    pub created: Vec<String>,
+    pub failed: Vec<String>,
+    pub existing: usize,
+}
+
+impl ProvisionReport {
+    pub fn describe(&self) -> String {
+        let mut lines = vec![format!("{} roles already existed.", self.existing)];
+        if !self.created.is_empty() {
+            lines.push(format!("Created: {}", self.created.join(", ")));
+        }
+        if !self.failed.is_empty() {
+            lines.push(format!("Could not create: {}. Check the logs for details.", self.failed.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+/**
+  Creates every requestable role, and the organizer role, that the guild
+  lacks. Roles are matched by name, ignoring case
+*/
+pub async fn provision_roles(http: &HttpClient, guild: GuildId) -> Result<ProvisionReport> {
+    let guild_roles = guild_roles(http, guild).await?;
+    let mut wanted = PersistentState::instance().lock().unwrap().requestable_roles.clone();
+    wanted.insert(0, organizer_role());
+
+    let mut report = ProvisionReport { created: Vec::new(), failed: Vec::new(), existing: 0 };
+    for role in wanted {
+        if guild_roles.iter().any(|existing| existing.name.to_lowercase() == role.name.to_lowercase()) {
+            report.existing += 1;
+            continue;
+        }
+        let mut request = http.create_role(guild)
+            .name(role.name.clone())
+            .mentionable(role.mentionable);
+        if let Some(color) = role.color {
+            request = request.color(color);
+        }
+        match request.await {
+            Ok(_) => {
+                println!("Created missing role {} in guild {}", role.name, guild);
+                report.created.push(role.name);
+            }
+            Err(e) => {
+                println!("Failed to create role {} in guild {}: {:?}", role.name, guild, e);
+                report.failed.push(role.name);
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Provisions the roles of a guild the bot has just connected to
+pub async fn handle_guild_available(http: &HttpClient, guild: GuildId) -> Result<()> {
+    let report = provision_roles(http, guild).await?;
+    if report.created.is_empty() && report.failed.is_empty() {
+        println!("All roles exist in guild {}", guild);
+    }
+    else {
+        send_alert(http, format!("Checked the server roles at startup.\n{}", report.describe())).await?;
+    }
+    Ok(())
+}
+
+pub async fn handle_setup(
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    // The server owner may run the setup even before the organizer role exists
+    let is_owner = http.guild(guild).await?
+        .map(|guild| guild.owner_id == author.id)
+        .unwrap_or(false);
+    if is_owner || has_permission(&http, guild, author.id, "setup").await? {
+        let report = provision_roles(&http, guild).await?;
+        send_message(&http, original_channel, author.id, report.describe()).await?;
+    }
+    else {
+        permission_denied(&http, original_channel, author.id, "setup", "set up the server").await?;
+    }
+    Ok(())
+}
@@ -10,8 +10,18 @@ use serde_derive::{Serialize, Deserialize};
 use serde_json;
 use twilight::model::id::{ChannelId, MessageId, UserId};
 
+use crate::capacity::DISCORD_CHANNEL_LIMIT;
 use crate::channel::Team;
+use crate::devlog::CheckinSchedule;
+use crate::expiry::RoleExpiry;
+use crate::lft::LftEntry;
+use crate::reaction::ReactionRoleMessage;
+use crate::recruit::{JoinRequest, RecruitPost};
+use crate::role::ReactionGrant;
+use crate::roles::{RoleEntry, default_roles};
+use crate::stats::RoleSignup;
 use crate::utils::Result;
+use crate::voice::VoiceConfig;
 
 const FILENAME: &'static str = "state.json";
 
@@ -27,8 +37,64 @@ const FILENAME: &'static str = "state.json";
 pub struct PersistentState {
     pub theme_ideas: HashMap<UserId, String>,
     pub channel_creators: HashMap<UserId, Team>,
-    role_assign_channel_id: ChannelId,
-    role_assign_message_id: MessageId,
+    /// The single role assignment message of older state files, moved into `reaction_messages` on load
+    #[serde(default, skip_serializing)]
+    role_assign_channel_id: Option<ChannelId>,
+    #[serde(default, skip_serializing)]
+    role_assign_message_id: Option<MessageId>,
+    /// Messages that members react to in order to get roles
+    #[serde(default)]
+    pub reaction_messages: HashMap<MessageId, ReactionRoleMessage>,
+    /// The channel where organizers are notified of things needing their attention
+    #[serde(default)]
+    pub alert_channel_id: Option<ChannelId>,
+    /// The maximum number of channels the guild may use before team creation is refused
+    #[serde(default = "default_channel_budget")]
+    pub channel_budget: u64,
+    /// The highest channel usage warning threshold, in percent, that organizers have been warned about
+    #[serde(default)]
+    pub channel_usage_warned: u64,
+    /// Settings for the voice channels of teams
+    #[serde(default)]
+    pub voice_config: VoiceConfig,
+    /// Team categories are sorted between these two marker categories
+    #[serde(default)]
+    pub team_categories_after: Option<ChannelId>,
+    #[serde(default)]
+    pub team_categories_before: Option<ChannelId>,
+    /// Unix timestamp after which game submissions are marked as late
+    #[serde(default)]
+    pub submission_deadline: Option<u64>,
+    /// Solo jammers looking for a team
+    #[serde(default)]
+    pub looking_for_team: HashMap<UserId, LftEntry>,
+    #[serde(default)]
+    pub checkin_schedule: Option<CheckinSchedule>,
+    /// Roles that users may request for themselves
+    #[serde(default = "default_roles")]
+    pub requestable_roles: Vec<RoleEntry>,
+    /// History of requestable roles being given and removed
+    #[serde(default)]
+    pub role_signups: Vec<RoleSignup>,
+    /// Roles to remove from users at a given time
+    #[serde(default)]
+    pub role_expiries: Vec<RoleExpiry>,
+    /// The roles allowed to use each privileged command, for commands not limited to organizers
+    #[serde(default)]
+    pub command_permissions: HashMap<String, Vec<String>>,
+    /// Posts of team owners that others react to in order to join the team
+    #[serde(default)]
+    pub recruit_posts: HashMap<MessageId, RecruitPost>,
+    /// Join requests awaiting an answer, keyed by the PM sent to the team owner
+    #[serde(default)]
+    pub join_requests: HashMap<MessageId, JoinRequest>,
+    /// Roles members got by reacting, which syncing may take away again
+    #[serde(default)]
+    pub reaction_grants: Vec<ReactionGrant>,
+}
+
+fn default_channel_budget() -> u64 {
+    DISCORD_CHANNEL_LIMIT
 }
 
 impl PersistentState {
@@ -38,14 +104,33 @@ impl PersistentState {
             let mut file = File::open(FILENAME)?;
             let mut content = String::new();
             file.read_to_string(&mut content)?;
-            Ok(serde_json::from_str(&content)?)
+            let mut state: Self = serde_json::from_str(&content)?;
+            state.migrate_role_assign();
+            Ok(state)
         }
         else {
             Ok(Self {
                 theme_ideas: HashMap::new(),
                 channel_creators: HashMap::new(),
-                role_assign_channel_id: ChannelId(0),
-                role_assign_message_id: MessageId(0),
+                role_assign_channel_id: None,
+                role_assign_message_id: None,
+                reaction_messages: HashMap::new(),
+                alert_channel_id: None,
+                channel_budget: default_channel_budget(),
+                channel_usage_warned: 0,
+                voice_config: VoiceConfig::default(),
+                team_categories_after: None,
+                team_categories_before: None,
+                submission_deadline: None,
+                looking_for_team: HashMap::new(),
+                checkin_schedule: None,
+                requestable_roles: default_roles(),
+                role_signups: Vec::new(),
+                role_expiries: Vec::new(),
+                command_permissions: HashMap::new(),
+                recruit_posts: HashMap::new(),
+                join_requests: HashMap::new(),
+                reaction_grants: Vec::new(),
             })
         }
     }
@@ -76,6 +161,11 @@ impl PersistentState {
     /// Registers that the user has created a channel
     pub fn register_channel_creation(&mut self, user_id: UserId, team: &Team) -> Result<()> {
         self.channel_creators.insert(user_id, team.clone());
+        // Team members are no longer looking for a team
+        self.looking_for_team.remove(&user_id);
+        for member in &team.members {
+            self.looking_for_team.remove(member);
+        }
         self.save()
     }
 
@@ -85,20 +175,25 @@ impl PersistentState {
         self.save()
     }
 
-    /// Sets the role assignment message
-    pub fn set_role_assign(&mut self, channel_id: ChannelId, message_id: MessageId) -> Result<()> {
-        self.role_assign_channel_id = channel_id;
-        self.role_assign_message_id = message_id;
-        self.save()
-    }
-    /// Gets the channel containing the role assignment message
-    pub fn get_role_assign_channel(&mut self) -> ChannelId {
-        self.role_assign_channel_id
+    /**
+      Turns the role assignment message of older state files into a
+      reaction-role message for every requestable role with an emoji
+    */
+    fn migrate_role_assign(&mut self) {
+        let channel_id = self.role_assign_channel_id.take();
+        let message_id = self.role_assign_message_id.take();
+        if let (Some(channel_id), Some(message_id)) = (channel_id, message_id) {
+            if message_id.0 != 0 && !self.reaction_messages.contains_key(&message_id) {
+                let bindings = self.default_bindings(None);
+                self.reaction_messages.insert(message_id, ReactionRoleMessage { channel_id, bindings, managed: false });
+            }
+        }
     }
 
-    /// Gets the role assignment message
-    pub fn get_role_assign_message(&mut self) -> MessageId {
-        self.role_assign_message_id
+    /// Sets the channel where organizers receive alerts
+    pub fn set_alert_channel(&mut self, channel_id: ChannelId) -> Result<()> {
+        self.alert_channel_id = Some(channel_id);
+        self.save()
     }
 
     /// Save the state to disk. Should be called after all modifications
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use serde_derive::{Serialize, Deserialize};
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        id::{ChannelId, GuildId, UserId},
+        user::User,
+    },
+};
+
+use crate::permissions::{has_permission, permission_denied};
+use crate::role_cache::guild_roles;
+use crate::state::PersistentState;
+use crate::utils::{
+    MAX_MESSAGE_LENGTH, Result, fetch_all_members, send_message, split_message,
+};
+
+/// A requestable role being given to or removed from a user
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoleSignup {
+    pub timestamp: u64,
+    pub user: UserId,
+    pub role: String,
+    /// Whether the role was given or removed
+    pub added: bool,
+}
+
+/**
+  Formats the sign-up history as CSV, with the running member count of
+  the role after every change.
+
+  Members who held a role before tracking began, or got it outside of the
+  bot, have no sign-up. The count before the first change is therefore
+  worked out backwards from `current_counts`, which are keyed by the
+  lowercased role name, and raised where needed so it never goes negative
+*/
+fn signups_to_csv(signups: &[RoleSignup], current_counts: &HashMap<String, i64>) -> String {
+    // The net change and the lowest running change of every role
+    let mut changes: HashMap<String, (i64, i64)> = HashMap::new();
+    for signup in signups {
+        let (net, lowest) = changes.entry(signup.role.to_lowercase()).or_insert((0, 0));
+        *net += if signup.added { 1 } else { -1 };
+        *lowest = (*lowest).min(*net);
+    }
+    let mut totals = changes.into_iter()
+        .map(|(role, (net, lowest))| {
+            let current = current_counts.get(&role).cloned().unwrap_or(0);
+            let initial = (current - net).max(-lowest).max(0);
+            (role, initial)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut csv = "timestamp,user_id,role,change,total".to_string();
+    for signup in signups {
+        let total = totals.get_mut(&signup.role.to_lowercase()).unwrap();
+        *total += if signup.added { 1 } else { -1 };
+        csv.push_str(&format!(
+            "\n{},{},\"{}\",{},{}",
+            signup.timestamp, signup.user, signup.role.replace('"', "\"\""),
+            if signup.added { "+1" } else { "-1" }, total
+        ));
+    }
+    csv
+}
+
+pub async fn handle_role_stats<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "rolestats").await? {
+        permission_denied(&http, original_channel, author.id, "rolestats", "view role statistics").await?;
+        return Ok(());
+    }
+
+    let guild_roles = guild_roles(&http, guild).await?;
+    let members = fetch_all_members(&http, guild).await?;
+
+    let (csv, lines) = {
+        let ps = PersistentState::instance().lock().unwrap();
+        let holders_of = |role_name: &str| {
+            let role_id = guild_roles.iter()
+                .find(|role| role.name.to_lowercase() == role_name.to_lowercase())
+                .map(|role| role.id);
+            members.iter()
+                .filter(|member| role_id.map(|id| member.roles.contains(&id)).unwrap_or(false))
+                .collect::<Vec<_>>()
+        };
+
+        if rest_command == ["csv"] {
+            let mut roles = ps.role_signups.iter().map(|signup| signup.role.to_lowercase()).collect::<Vec<_>>();
+            roles.sort();
+            roles.dedup();
+            let current_counts = roles.into_iter()
+                .map(|role| {
+                    let count = holders_of(role.as_str()).len() as i64;
+                    (role, count)
+                })
+                .collect();
+            (Some(signups_to_csv(&ps.role_signups, &current_counts)), Vec::new())
+        }
+        else {
+            let lines = ps.requestable_roles.iter()
+                .map(|requestable| {
+                    let holders = holders_of(&requestable.name);
+                    let in_team = holders.iter()
+                        .filter(|member| ps.team_of(member.user.id).is_some())
+                        .count();
+                    format!(
+                        "**{}**: {} members, {} in a team and {} solo",
+                        requestable.name, holders.len(), in_team, holders.len() - in_team
+                    )
+                })
+                .collect::<Vec<_>>();
+            (None, lines)
+        }
+    };
+
+    if let Some(csv) = csv {
+        // Leave room for the code block around every chunk
+        for chunk in split_message(&csv, MAX_MESSAGE_LENGTH - 10) {
+            send_message(&http, original_channel, author.id, format!("```csv\n{}```", chunk)).await?;
+        }
+        return Ok(());
+    }
+
+    send_message(&http, original_channel, author.id,
+        format!(
+            "Role statistics:\n{}\n\nUse `!rolestats csv` to export the sign-up history.",
+            lines.join("\n")
+        )
+    ).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signup(timestamp: u64, user: u64, role: &str, added: bool) -> RoleSignup {
+        RoleSignup { timestamp, user: UserId(user), role: role.to_string(), added }
+    }
+
+    fn counts(counts: &[(&str, i64)]) -> HashMap<String, i64> {
+        counts.iter()
+            .map(|(role, count)| (role.to_string(), *count))
+            .collect()
+    }
+
+    fn totals(csv: &str) -> Vec<String> {
+        csv.lines()
+            .skip(1)
+            .map(|line| line.rsplit(',').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn totals_are_seeded_from_current_counts() {
+        let signups = [signup(1, 10, "Programmer", true), signup(2, 11, "Programmer", true)];
+        let csv = signups_to_csv(&signups, &counts(&[("programmer", 5)]));
+        assert_eq!(csv.lines().next(), Some("timestamp,user_id,role,change,total"));
+        assert_eq!(csv.lines().nth(1), Some("1,10,\"Programmer\",+1,4"));
+        assert_eq!(totals(&csv), vec!["4", "5"]);
+    }
+
+    #[test]
+    fn removals_count_down_to_the_current_count() {
+        let signups = [signup(1, 10, "Musician", false), signup(2, 11, "Musician", false)];
+        let csv = signups_to_csv(&signups, &counts(&[("musician", 2)]));
+        assert_eq!(totals(&csv), vec!["3", "2"]);
+        assert_eq!(csv.lines().nth(1), Some("1,10,\"Musician\",-1,3"));
+    }
+
+    #[test]
+    fn removals_of_roles_held_before_tracking_never_go_negative() {
+        let signups = [
+            signup(1, 10, "Musician", false),
+            signup(2, 11, "Musician", true),
+            signup(3, 11, "Musician", false),
+        ];
+        let csv = signups_to_csv(&signups, &counts(&[("musician", 0)]));
+        assert_eq!(totals(&csv), vec!["0", "1", "0"]);
+    }
+
+    #[test]
+    fn roles_missing_from_current_counts_start_at_zero() {
+        let signups = [signup(1, 10, "Idea Guy", true), signup(2, 10, "Idea Guy", false)];
+        let csv = signups_to_csv(&signups, &HashMap::new());
+        assert_eq!(totals(&csv), vec!["1", "0"]);
+    }
+
+    #[test]
+    fn roles_without_history_have_no_rows() {
+        let csv = signups_to_csv(&[], &counts(&[("programmer", 3)]));
+        assert_eq!(csv, "timestamp,user_id,role,change,total");
+    }
+
+    #[test]
+    fn role_names_are_matched_ignoring_case() {
+        let signups = [signup(1, 10, "programmer", true), signup(2, 11, "Programmer", true)];
+        let csv = signups_to_csv(&signups, &counts(&[("programmer", 2)]));
+        assert_eq!(totals(&csv), vec!["1", "2"]);
+    }
+
+    #[test]
+    fn quotes_in_role_names_are_escaped() {
+        let signups = [signup(1, 10, "The \"Best\" Role", true)];
+        let csv = signups_to_csv(&signups, &counts(&[("the \"best\" role", 1)]));
+        assert_eq!(csv.lines().nth(1), Some("1,10,\"The \"\"Best\"\" Role\",+1,1"));
+    }
+}
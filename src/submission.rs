@@ -0,0 +1,174 @@
+use serde_derive::{Serialize, Deserialize};
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        id::{ChannelId, GuildId},
+        user::User,
+    },
+};
+
+use crate::permissions::{has_permission, permission_denied};
+use crate::state::PersistentState;
+use crate::utils::{
+    Result, format_duration, format_timestamp, parse_duration, send_long_message, send_message,
+    unix_now,
+};
+
+/// A game submitted by a team
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Submission {
+    pub url: String,
+    pub description: String,
+    /// Unix timestamp of the latest submission
+    pub submitted_at: u64,
+    /// Whether the game was submitted after the deadline
+    pub late: bool,
+}
+
+pub async fn handle_submit_game<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    // Discord users often wrap links in <> to avoid embeds
+    let url = rest_command.get(0).map(|url| url.trim_start_matches('<').trim_end_matches('>'));
+    let url = match url {
+        Some(url) if url.starts_with("https://") || url.starts_with("http://") => url.to_string(),
+        _ => {
+            send_message(&http, original_channel, author.id,
+                "Proper usage: `!submitgame <link to your game> [description]`"
+            ).await?;
+            return Ok(());
+        }
+    };
+    let description = rest_command[1..].join(" ");
+
+    let now = unix_now();
+    let result = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let deadline = ps.submission_deadline;
+        match ps.team_of(author.id) {
+            Some(creator) => {
+                let late = deadline.map(|deadline| now > deadline).unwrap_or(false);
+                let team = ps.channel_creators.get_mut(&creator).unwrap();
+                let resubmitted = team.submission.is_some();
+                team.submission = Some(Submission {
+                    url: url.clone(),
+                    description,
+                    submitted_at: now,
+                    late,
+                });
+                let game_name = team.game_name.clone();
+                ps.save()?;
+                Some((game_name, resubmitted, late, deadline))
+            }
+            None => None,
+        }
+    };
+
+    let reply = match result {
+        None => "You need to be part of a team to submit a game.\n\
+            Try using `!createchannels <game name>` to create one.".to_string(),
+        Some((game_name, resubmitted, late, deadline)) => {
+            let action = if resubmitted { "updated" } else { "submitted" };
+            if late {
+                println!("{} submitted {} after the deadline", author.name, game_name);
+                format!(
+                    "**{}** has been {} with the link <{}>, but the deadline passed {} ago \
+                    so it is marked as late.",
+                    game_name, action, url, format_duration(now - deadline.unwrap())
+                )
+            }
+            else {
+                format!("**{}** has been {} with the link <{}>. Good job!", game_name, action, url)
+            }
+        }
+    };
+    send_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
+
+pub async fn handle_list_games(
+    original_channel: ChannelId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    let (mut games, deadline) = {
+        let ps = PersistentState::instance().lock().unwrap();
+        let games = ps.channel_creators.values()
+            .filter_map(|team| team.submission.as_ref().map(|submission| (team.game_name.clone(), submission.clone())))
+            .collect::<Vec<_>>();
+        (games, ps.submission_deadline)
+    };
+    games.sort_by_key(|(game_name, _)| game_name.to_lowercase());
+
+    let deadline_info = match deadline {
+        Some(deadline) => format!("The deadline is {}.", format_timestamp(deadline)),
+        None => "No deadline has been set.".to_string(),
+    };
+    let message = if games.is_empty() {
+        format!("No games have been submitted yet. {}", deadline_info)
+    }
+    else {
+        let list = games.iter()
+            .map(|(game_name, submission)| {
+                let late = if submission.late { " *(late)*" } else { "" };
+                let description = if submission.description.is_empty() {
+                    String::new()
+                }
+                else {
+                    format!(" - {}", submission.description)
+                };
+                format!("**{}**{}: <{}>{}", game_name, late, submission.url, description)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("{} games have been submitted. {}\n{}", games.len(), deadline_info, list)
+    };
+    send_long_message(&http, original_channel, author.id, message).await?;
+    Ok(())
+}
+
+pub async fn handle_set_deadline<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "setdeadline").await? {
+        permission_denied(&http, original_channel, author.id, "setdeadline", "set the submission deadline").await?;
+        return Ok(());
+    }
+
+    // Either a unix timestamp or a duration from now, such as `+48h`
+    let deadline = match rest_command.get(0) {
+        Some(&"none") => Some(None),
+        Some(arg) if arg.starts_with('+') => parse_duration(&arg[1..]).map(|duration| Some(unix_now() + duration)),
+        Some(arg) => arg.parse::<u64>().ok().map(Some),
+        None => None,
+    };
+
+    match deadline {
+        Some(deadline) => {
+            {
+                let mut ps = PersistentState::instance().lock().unwrap();
+                ps.submission_deadline = deadline;
+                ps.save()?;
+            }
+            let reply = match deadline {
+                Some(deadline) => format!("The submission deadline is now {}.", format_timestamp(deadline)),
+                None => "The submission deadline has been removed.".to_string(),
+            };
+            send_message(&http, original_channel, author.id, reply).await?;
+        }
+        None => {
+            send_message(&http, original_channel, author.id,
+                "Proper usage: `!setdeadline <unix timestamp|+duration|none>`, \
+                for example `!setdeadline +48h`."
+            ).await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,334 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        id::{ChannelId, GuildId, RoleId, UserId},
+        user::User,
+    },
+};
+
+use crate::permissions::{has_permission, permission_denied};
+use crate::role::{ReactionGrant, RoleError, RoleSource, remove_role, set_exclusive_role};
+use crate::role_cache::find_role;
+use crate::state::PersistentState;
+use crate::utils::{Result, fetch_all_members, fetch_reaction_users, send_alert, send_long_message, send_message};
+
+/// What reconciling reactions and roles is allowed to change
+#[derive(Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    /// Only report the differences
+    DryRun,
+    /// Give and remove roles until they match the reactions
+    Apply,
+}
+
+/// A role given by reacting on a role assignment message, and who reacted for it
+pub struct ReactedRole {
+    pub id: RoleId,
+    pub name: String,
+    pub exclusive_group: Option<String>,
+    pub reacted: HashSet<UserId>,
+}
+
+/// The differences found between reactions and roles, and what was done about them
+pub struct SyncReport {
+    /// Members who reacted but lack the role
+    pub missing: Vec<(UserId, String)>,
+    /// Members who got the role through a reaction they have since removed
+    pub unreacted: Vec<(UserId, String)>,
+    /// Members who reacted for several roles of an exclusive group, which are left alone
+    pub conflicts: Vec<(UserId, Vec<String>)>,
+    pub mode: SyncMode,
+    pub failed: usize,
+}
+
+impl SyncReport {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unreacted.is_empty() && self.conflicts.is_empty()
+    }
+
+    pub fn describe(&self) -> String {
+        if self.is_empty() {
+            return "All roles match the reactions on the role assignment messages.".to_string();
+        }
+        let format_list = |changes: &[(UserId, String)]| changes.iter()
+            .map(|(user, role)| format!("<@{}> **{}**", user, role))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut lines = Vec::new();
+        if !self.missing.is_empty() {
+            let verb = if self.mode == SyncMode::DryRun { "Would give" } else { "Gave" };
+            lines.push(format!("{} roles to members who reacted: {}", verb, format_list(&self.missing)));
+        }
+        if !self.unreacted.is_empty() {
+            let verb = if self.mode == SyncMode::DryRun { "Would remove" } else { "Removed" };
+            lines.push(format!("{} roles whose reaction was removed: {}", verb, format_list(&self.unreacted)));
+        }
+        if !self.conflicts.is_empty() {
+            let conflicts = self.conflicts.iter()
+                .map(|(user, roles)| format!("<@{}> ({})", user, roles.join(", ")))
+                .collect::<Vec<_>>()
+                .join(", ");
+            lines.push(format!(
+                "Left alone members who reacted for several roles that exclude each other: {}", conflicts
+            ));
+        }
+        if self.failed > 0 {
+            lines.push(format!("{} changes failed. Check the logs for details.", self.failed));
+        }
+        lines.join("\n")
+    }
+}
+
+/**
+  Works out which roles to give and remove so that roles match reactions.
+
+  Roles are only removed if the member got them through a reaction, as
+  listed in `grants`, since roles requested with commands never had one.
+  Members who reacted for several roles of one exclusive group are
+  reported as conflicts instead of being given any role of that group
+*/
+fn diff_reaction_roles(
+    roles: &[ReactedRole],
+    members: &[(UserId, Vec<RoleId>)],
+    grants: &HashSet<(UserId, RoleId)>,
+    mode: SyncMode,
+) -> SyncReport {
+    let mut report = SyncReport { missing: Vec::new(), unreacted: Vec::new(), conflicts: Vec::new(), mode, failed: 0 };
+    for (user, held) in members {
+        let mut reacted_by_group: HashMap<&str, Vec<&ReactedRole>> = HashMap::new();
+        for role in roles.iter().filter(|role| role.reacted.contains(user)) {
+            if let Some(group) = &role.exclusive_group {
+                reacted_by_group.entry(group.as_str()).or_insert_with(Vec::new).push(role);
+            }
+        }
+        let conflicting_groups = reacted_by_group.iter()
+            .filter(|(_, reacted)| reacted.len() > 1)
+            .map(|(group, _)| *group)
+            .collect::<HashSet<_>>();
+        let mut conflicting_roles = Vec::new();
+
+        for role in roles {
+            let reacted = role.reacted.contains(user);
+            let has_role = held.contains(&role.id);
+            let in_conflict = role.exclusive_group.as_ref()
+                .map(|group| conflicting_groups.contains(group.as_str()))
+                .unwrap_or(false);
+            if reacted && in_conflict {
+                conflicting_roles.push(role.name.clone());
+            }
+            else if reacted && !has_role {
+                report.missing.push((*user, role.name.clone()));
+            }
+            else if !reacted && has_role && grants.contains(&(*user, role.id)) {
+                report.unreacted.push((*user, role.name.clone()));
+            }
+        }
+        if !conflicting_roles.is_empty() {
+            report.conflicts.push((*user, conflicting_roles));
+        }
+    }
+    report
+}
+
+/**
+  Compares the reactions on every role assignment message in the guild
+  with the roles of its members, and gives or removes roles if `mode`
+  allows it. Roles bound on several messages count as reacted if any of
+  them has the member's reaction.
+
+  Fails without changing anything if any reactions can't be fetched,
+  since members would otherwise look like they had removed their reaction
+*/
+pub async fn sync_reaction_roles(http: &HttpClient, guild: GuildId, mode: SyncMode) -> Result<SyncReport> {
+    let guild_channels = http.guild_channels(guild).await?
+        .iter()
+        .map(|channel| channel.id())
+        .collect::<HashSet<ChannelId>>();
+    let messages = PersistentState::instance().lock().unwrap()
+        .reaction_messages.iter()
+        .filter(|(_, message)| guild_channels.contains(&message.channel_id))
+        .map(|(message_id, message)| (*message_id, message.clone()))
+        .collect::<Vec<_>>();
+
+    let mut reacted: HashMap<String, HashSet<UserId>> = HashMap::new();
+    for (message_id, message) in messages {
+        for binding in &message.bindings {
+            let users = fetch_reaction_users(http, message.channel_id, message_id, &binding.emoji.reaction_string())
+                .await
+                .with_context(|| format!("Failed to fetch reactions {} on message {}", binding.emoji, message_id))?;
+            reacted.entry(binding.role.clone())
+                .or_insert_with(HashSet::new)
+                .extend(users.into_iter().filter(|user| !user.bot).map(|user| user.id));
+        }
+    }
+
+    let mut roles = Vec::new();
+    for (role_name, reacted_users) in reacted {
+        match find_role(http, guild, &role_name).await? {
+            Some(role) => {
+                let exclusive_group = PersistentState::instance().lock().unwrap()
+                    .find_requestable_role(&role.name)
+                    .and_then(|requestable| requestable.exclusive_group.clone());
+                roles.push(ReactedRole { id: role.id, name: role.name, exclusive_group, reacted: reacted_users });
+            }
+            None => println!("Not syncing role {}, which doesn't exist in guild {}", role_name, guild),
+        }
+    }
+
+    let members = fetch_all_members(http, guild).await?
+        .into_iter()
+        .filter(|member| !member.user.bot)
+        .map(|member| (member.user.id, member.roles))
+        .collect::<Vec<_>>();
+    let grants = PersistentState::instance().lock().unwrap()
+        .reaction_grants.iter()
+        .filter(|grant| grant.guild_id == guild)
+        .map(|ReactionGrant { user_id, role_id, .. }| (*user_id, *role_id))
+        .collect::<HashSet<_>>();
+
+    let mut report = diff_reaction_roles(&roles, &members, &grants, mode);
+    if mode == SyncMode::Apply {
+        for (user, role) in &report.missing {
+            // The same as reacting, so other roles of an exclusive group are replaced
+            if let Err(e) = set_exclusive_role(http, guild, *user, role, RoleSource::Reaction).await {
+                println!("Failed to give {} the role {} while syncing: {}", user, role, e);
+                report.failed += 1;
+            }
+        }
+        for (user, role) in &report.unreacted {
+            match remove_role(http, guild, *user, role).await {
+                // Giving a missing role of the same exclusive group already removed it
+                Ok(_) | Err(RoleError::NotSet(_)) => {}
+                Err(e) => {
+                    println!("Failed to remove the role {} from {} while syncing: {}", role, user, e);
+                    report.failed += 1;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Catches up on reactions added or removed while the bot was offline
+pub async fn sync_after_downtime(http: &HttpClient, guild: GuildId) -> Result<()> {
+    let report = sync_reaction_roles(http, guild, SyncMode::Apply).await?;
+    if report.is_empty() {
+        println!("Roles match the reactions in guild {}", guild);
+    }
+    else {
+        send_alert(http, format!(
+            "Synced roles with the role assignment reactions at startup.\n{}", report.describe()
+        )).await?;
+    }
+    Ok(())
+}
+
+pub async fn handle_sync_roles<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "syncroles").await? {
+        permission_denied(&http, original_channel, author.id, "syncroles", "sync roles with reactions").await?;
+        return Ok(());
+    }
+
+    let mode = match rest_command {
+        [] => SyncMode::DryRun,
+        ["apply"] => SyncMode::Apply,
+        _ => {
+            send_message(&http, original_channel, author.id,
+                "Proper usage: `!syncroles` to list the differences, or `!syncroles apply` to fix them"
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    let report = match sync_reaction_roles(&http, guild, mode).await {
+        Ok(report) => report,
+        Err(e) => {
+            println!("Failed to sync roles with reactions: {:?}", e);
+            send_message(&http, original_channel, author.id,
+                "Could not read all reactions, so no roles were changed. Check the logs for details."
+            ).await?;
+            return Ok(());
+        }
+    };
+    println!("{} synced roles with reactions, {} missing, {} unreacted and {} conflicts",
+        author.name, report.missing.len(), report.unreacted.len(), report.conflicts.len()
+    );
+    let mut reply = report.describe();
+    if mode == SyncMode::DryRun && (!report.missing.is_empty() || !report.unreacted.is_empty()) {
+        reply.push_str("\nUse `!syncroles apply` to make these changes.");
+    }
+    send_long_message(&http, original_channel, author.id, reply).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role(id: u64, name: &str, group: Option<&str>, reacted: &[u64]) -> ReactedRole {
+        ReactedRole {
+            id: RoleId(id),
+            name: name.to_string(),
+            exclusive_group: group.map(str::to_string),
+            reacted: reacted.iter().map(|user| UserId(*user)).collect(),
+        }
+    }
+
+    fn member(user: u64, roles: &[u64]) -> (UserId, Vec<RoleId>) {
+        (UserId(user), roles.iter().map(|role| RoleId(*role)).collect())
+    }
+
+    fn change(user: u64, role: &str) -> (UserId, String) {
+        (UserId(user), role.to_string())
+    }
+
+    #[test]
+    fn reacted_members_lacking_the_role_are_missing() {
+        let roles = [role(10, "Programmer", None, &[1, 2])];
+        let members = [member(1, &[10]), member(2, &[]), member(3, &[])];
+        let report = diff_reaction_roles(&roles, &members, &HashSet::new(), SyncMode::DryRun);
+        assert_eq!(report.missing, vec![change(2, "Programmer")]);
+        assert!(report.unreacted.is_empty());
+    }
+
+    #[test]
+    fn only_roles_granted_by_reactions_are_removed() {
+        let roles = [role(10, "Programmer", None, &[])];
+        let members = [member(1, &[10]), member(2, &[10])];
+        let grants = [(UserId(1), RoleId(10))].iter().cloned().collect();
+        let report = diff_reaction_roles(&roles, &members, &grants, SyncMode::Apply);
+        assert_eq!(report.unreacted, vec![change(1, "Programmer")]);
+        assert!(report.missing.is_empty());
+    }
+
+    #[test]
+    fn several_reactions_in_an_exclusive_group_conflict() {
+        let roles = [
+            role(10, "Team Red", Some("team"), &[1, 2]),
+            role(11, "Team Blue", Some("team"), &[1]),
+            role(12, "Programmer", None, &[1]),
+        ];
+        let members = [member(1, &[]), member(2, &[])];
+        let report = diff_reaction_roles(&roles, &members, &HashSet::new(), SyncMode::DryRun);
+        assert_eq!(report.missing, vec![change(1, "Programmer"), change(2, "Team Red")]);
+        assert_eq!(report.conflicts, vec![(UserId(1), vec!["Team Red".to_string(), "Team Blue".to_string()])]);
+    }
+
+    #[test]
+    fn matching_roles_report_nothing() {
+        let roles = [role(10, "Programmer", None, &[1])];
+        let members = [member(1, &[10]), member(2, &[])];
+        let grants = [(UserId(1), RoleId(10))].iter().cloned().collect();
+        assert!(diff_reaction_roles(&roles, &members, &grants, SyncMode::Apply).is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use twilight::{
+    http::Client as HttpClient,
+    model::{
+        channel::GuildChannel,
+        id::{ChannelId, GuildId},
+        user::User,
+    },
+};
+
+use crate::permissions::{has_permission, permission_denied};
+use crate::state::PersistentState;
+use crate::utils::{Result, parse_category, send_message};
+
+/**
+  Computes new positions for the categories of the guild such that team
+  categories are sorted alphabetically between the `after` and `before`
+  markers. Categories are numbered from 0 in their new order, and only
+  those whose real position differs from that number are returned, so
+  that gaps and duplicates in the current positions get fixed as well.
+
+  `categories` holds the ID and current position of every category and
+  `team_names` maps team category IDs to their game name
+*/
+fn sorted_category_positions(
+    categories: &[(ChannelId, i64)],
+    team_names: &HashMap<ChannelId, String>,
+    after: Option<ChannelId>,
+    before: Option<ChannelId>,
+) -> Vec<(ChannelId, u64)> {
+    let mut current = categories.to_vec();
+    current.sort_by_key(|(id, position)| (*position, id.0));
+
+    let (mut teams, mut others): (Vec<_>, Vec<_>) = current.iter()
+        .map(|(id, _)| *id)
+        .partition(|id| team_names.contains_key(id));
+    teams.sort_by_key(|id| team_names[id].to_lowercase());
+
+    let after_index = after.and_then(|after| others.iter().position(|id| *id == after));
+    let before_index = before.and_then(|before| others.iter().position(|id| *id == before));
+    let insert_at = match (after_index, before_index) {
+        (Some(after), Some(before)) if before > after => before,
+        (Some(after), _) => after + 1,
+        (None, Some(before)) => before,
+        (None, None) => others.len(),
+    };
+    others.splice(insert_at..insert_at, teams);
+
+    let real_positions = categories.iter().cloned().collect::<HashMap<_, _>>();
+    others.iter()
+        .enumerate()
+        .filter(|(position, id)| real_positions[*id] != *position as i64)
+        .map(|(position, id)| (*id, position as u64))
+        .collect()
+}
+
+/// Moves the team categories into alphabetical order between the configured markers
+pub async fn sort_team_categories(http: &HttpClient, guild: GuildId) -> Result<()> {
+    let categories = http.guild_channels(guild).await?
+        .into_iter()
+        .filter_map(|channel| match channel {
+            GuildChannel::Category(category) => Some((category.id, category.position)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let (team_names, after, before) = {
+        let ps = PersistentState::instance().lock().unwrap();
+        let team_names = ps.channel_creators.values()
+            .map(|team| (team.category_id, team.game_name.clone()))
+            .collect::<HashMap<_, _>>();
+        (team_names, ps.team_categories_after, ps.team_categories_before)
+    };
+
+    let positions = sorted_category_positions(&categories, &team_names, after, before);
+    if !positions.is_empty() {
+        http.update_guild_channel_positions(guild, positions.into_iter()).await?;
+    }
+    Ok(())
+}
+
+pub async fn handle_sort_teams(
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if has_permission(&http, guild, author.id, "sortteams").await? {
+        match sort_team_categories(&http, guild).await {
+            Ok(_) => {
+                send_message(&http, original_channel, author.id,
+                    "The team categories have been sorted."
+                ).await?;
+            }
+            Err(e) => {
+                send_message(&http, original_channel, author.id,
+                    "Could not sort the team categories. Check the logs for details."
+                ).await?;
+                println!("Failed to sort team categories: {:?}", e);
+            }
+        }
+    }
+    else {
+        permission_denied(&http, original_channel, author.id, "sortteams", "sort the team categories").await?;
+    }
+    Ok(())
+}
+
+pub async fn handle_set_team_markers<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "setteammarkers").await? {
+        permission_denied(&http, original_channel, author.id, "setteammarkers", "set the team category markers").await?;
+        return Ok(());
+    }
+
+    // Category names may contain spaces, so the two markers are separated by a `|`
+    let args = rest_command.join(" ");
+    let mut markers = args.split('|').map(str::trim);
+    let (after, before) = match (markers.next(), markers.next(), markers.next()) {
+        (Some(after), Some(before), None) if !after.is_empty() && !before.is_empty() => (
+            parse_category(&http, guild, after).await?,
+            parse_category(&http, guild, before).await?,
+        ),
+        _ => {
+            send_message(&http, original_channel, author.id,
+                "Proper usage: `!setteammarkers <category before teams> | <category after teams>`"
+            ).await?;
+            return Ok(());
+        }
+    };
+
+    match (after, before) {
+        (Some(after), Some(before)) => {
+            {
+                let mut ps = PersistentState::instance().lock().unwrap();
+                ps.team_categories_after = Some(after);
+                ps.team_categories_before = Some(before);
+                ps.save()?;
+            }
+            send_message(&http, original_channel, author.id,
+                format!("Team categories will be placed between <#{}> and <#{}>.", after, before)
+            ).await?;
+            if let Err(e) = sort_team_categories(&http, guild).await {
+                println!("Failed to sort team categories: {:?}", e);
+            }
+        }
+        _ => {
+            send_message(&http, original_channel, author.id,
+                "Could not find both categories. Use their names or IDs."
+            ).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn team_names(names: &[(u64, &str)]) -> HashMap<ChannelId, String> {
+        names.iter()
+            .map(|(id, name)| (ChannelId(*id), name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn teams_are_sorted_between_markers() {
+        let categories = [(ChannelId(1), 0), (ChannelId(2), 1), (ChannelId(3), 2), (ChannelId(10), 3), (ChannelId(11), 4)];
+        let teams = team_names(&[(10, "Zebra"), (11, "apple")]);
+        let positions = sorted_category_positions(&categories, &teams, Some(ChannelId(2)), Some(ChannelId(3)));
+        assert_eq!(positions, vec![(ChannelId(11), 2), (ChannelId(3), 4)]);
+    }
+
+    #[test]
+    fn sorted_categories_are_left_alone() {
+        let categories = [(ChannelId(1), 0), (ChannelId(11), 1), (ChannelId(10), 2), (ChannelId(2), 3)];
+        let teams = team_names(&[(10, "Zebra"), (11, "Apple")]);
+        let positions = sorted_category_positions(&categories, &teams, Some(ChannelId(1)), Some(ChannelId(2)));
+        assert!(positions.is_empty());
+    }
+
+    #[test]
+    fn teams_go_last_without_markers() {
+        let categories = [(ChannelId(10), 0), (ChannelId(1), 1), (ChannelId(2), 2)];
+        let teams = team_names(&[(10, "Team")]);
+        let positions = sorted_category_positions(&categories, &teams, None, None);
+        assert_eq!(positions, vec![(ChannelId(1), 0), (ChannelId(2), 1), (ChannelId(10), 2)]);
+    }
+
+    #[test]
+    fn teams_go_before_the_only_marker() {
+        let categories = [(ChannelId(1), 0), (ChannelId(2), 1), (ChannelId(10), 2)];
+        let teams = team_names(&[(10, "Team")]);
+        let positions = sorted_category_positions(&categories, &teams, None, Some(ChannelId(2)));
+        assert_eq!(positions, vec![(ChannelId(10), 1), (ChannelId(2), 2)]);
+    }
+
+    #[test]
+    fn swapped_markers_put_teams_after_the_first() {
+        let categories = [(ChannelId(1), 0), (ChannelId(10), 1), (ChannelId(2), 2)];
+        let teams = team_names(&[(10, "Team")]);
+        let positions = sorted_category_positions(&categories, &teams, Some(ChannelId(2)), Some(ChannelId(1)));
+        assert_eq!(positions, vec![(ChannelId(2), 1), (ChannelId(10), 2)]);
+    }
+
+    #[test]
+    fn gapped_positions_are_renumbered() {
+        let categories = [(ChannelId(1), 0), (ChannelId(2), 5), (ChannelId(10), 10)];
+        let teams = team_names(&[(10, "Team")]);
+        let positions = sorted_category_positions(&categories, &teams, None, Some(ChannelId(2)));
+        assert_eq!(positions, vec![(ChannelId(10), 1), (ChannelId(2), 2)]);
+    }
+
+    #[test]
+    fn gapped_positions_are_renumbered_when_already_sorted() {
+        let categories = [(ChannelId(1), 0), (ChannelId(10), 5), (ChannelId(2), 5)];
+        let teams = team_names(&[(10, "Team")]);
+        let positions = sorted_category_positions(&categories, &teams, Some(ChannelId(1)), Some(ChannelId(2)));
+        assert_eq!(positions, vec![(ChannelId(10), 1), (ChannelId(2), 2)]);
+    }
+}
@@ -9,8 +9,7 @@ use twilight::{
     },
 };
 
-use crate::role::has_role;
-use crate::roles::ORGANIZER;
+use crate::permissions::{has_permission, permission_denied};
 use crate::state::PersistentState;
 use crate::utils::{Result, send_message};
 
@@ -91,12 +90,7 @@ pub async fn handle_generate_theme(
     author: &User,
     http: HttpClient
 ) -> Result<()> {
-    if has_role(
-        &http,
-        guild,
-        author.id,
-        ORGANIZER,
-    ).await? {
+    if has_permission(&http, guild, author.id, "generatetheme").await? {
         let theme = do_theme_generation();
         let send_result = send_message(&http, original_channel, author.id,
             &theme
@@ -115,12 +109,7 @@ pub async fn handle_generate_theme(
         }
     }
     else {
-        send_message(&http, original_channel, author.id,
-            format!(
-                "Since you lack the required role **{}**, you do \
-                not have permission to generate themes.", ORGANIZER)
-        ).await?;
-        println!("Tried to generate theme without required role \"{}\"", ORGANIZER);
+        permission_denied(&http, original_channel, author.id, "generatetheme", "generate themes").await?;
     }
 
     Ok(())
@@ -132,12 +121,7 @@ pub async fn handle_show_all_themes(
     author: &User,
     http: HttpClient
 ) -> Result<()> {
-    if has_role(
-        &http,
-        guild,
-        author.id,
-        ORGANIZER,
-    ).await? {
+    if has_permission(&http, guild, author.id, "showallthemes").await? {
         let all_ideas = format_all_ideas();
         let send_result = send_message(&http, original_channel, author.id,
             format!("The theme ideas submitted are ```{}```", all_ideas)
@@ -157,12 +141,7 @@ pub async fn handle_show_all_themes(
         }
     }
     else {
-        send_message(&http, original_channel, author.id,
-            format!(
-                "Since you lack the required role **{}**, you do \
-                not have permission to see all the theme ideas.", ORGANIZER)
-        ).await?;
-        println!("Tried to see all theme ideas without required role \"{}\"", ORGANIZER);
+        permission_denied(&http, original_channel, author.id, "showallthemes", "see all the theme ideas").await?;
     }
     Ok(())
 }
@@ -1,5 +1,8 @@
 use std::fmt::Display;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use lazy_static::lazy_static;
+use regex::Regex;
 use twilight::{
     http::{
         Client as HttpClient,
@@ -8,11 +11,25 @@ use twilight::{
             ResponseError,
         },
     },
-    model::id::{ChannelId, UserId},
+    model::{
+        channel::{GuildChannel, ReactionType},
+        guild::Member,
+        id::{ChannelId, GuildId, MessageId, RoleId, UserId},
+        user::User,
+    },
 };
 
+use crate::role_cache::guild_roles;
+use crate::state::PersistentState;
+
 pub type Result<T> = std::result::Result<T, anyhow::Error>;
 
+lazy_static! {
+    static ref USER_MENTION_REGEX: Regex = Regex::new(r"^<@!?(\d+)>$").unwrap();
+    static ref CHANNEL_MENTION_REGEX: Regex = Regex::new(r"^<#(\d+)>$").unwrap();
+    static ref ROLE_MENTION_REGEX: Regex = Regex::new(r"^<@&(\d+)>$").unwrap();
+}
+
 pub async fn send_message(
     http: &HttpClient,
     channel_id: ChannelId,
@@ -36,3 +53,359 @@ pub async fn send_message(
     };
     Ok(())
 }
+
+/// Checks if a request failed because what it refers to doesn't exist
+pub fn is_not_found(error: &DiscordError) -> bool {
+    match error {
+        DiscordError::Response{source: ResponseError::Client{response: r}} => r.status().as_u16() == 404,
+        _ => false,
+    }
+}
+
+/// Discord's limit on message length, minus some room for the mention added by `send_message`
+pub const MAX_MESSAGE_LENGTH: usize = 1900;
+
+/**
+  Sends content that may exceed Discord's message length limit, split
+  over several messages at line breaks
+*/
+pub async fn send_long_message(
+    http: &HttpClient,
+    channel_id: ChannelId,
+    user_id: UserId,
+    content: impl Into<String> + Display,
+) -> Result<()> {
+    for chunk in split_message(&content.into(), MAX_MESSAGE_LENGTH) {
+        send_message(http, channel_id, user_id, chunk).await?;
+    }
+    Ok(())
+}
+
+/// Splits content into chunks of at most `max_length` bytes, preferably at line breaks
+pub fn split_message(content: &str, max_length: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for line in content.lines() {
+        if !current.is_empty() && current.len() + line.len() + 1 > max_length {
+            chunks.push(std::mem::replace(&mut current, String::new()));
+        }
+        let mut line = line;
+        // Lines that are too long on their own are split at character boundaries
+        while line.len() > max_length {
+            let mut split = max_length;
+            while !line.is_char_boundary(split) {
+                split -= 1;
+            }
+            chunks.push(line[..split].to_string());
+            line = &line[split..];
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/**
+  Posts a message in the organizer alert channel, if one is set.
+  Alerts are always logged
+*/
+pub async fn send_alert(
+    http: &HttpClient,
+    content: impl Into<String> + Display,
+) -> Result<()> {
+    println!("Alert: {}", content);
+    let alert_channel = PersistentState::instance().lock().unwrap().alert_channel_id;
+    if let Some(channel_id) = alert_channel {
+        if let Err(e) = http.create_message(channel_id).content(content.into()).await {
+            println!("send_alert: Failed to send alert: {:?}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the current time as seconds since the unix epoch
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/**
+  Parses a duration such as `90m`, `12h` or `3d` into seconds. A number
+  without a unit is interpreted as minutes
+*/
+pub fn parse_duration(arg: &str) -> Option<u64> {
+    let split = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    let amount = arg[..split].parse::<u64>().ok()?;
+    let unit_seconds = match &arg[split..] {
+        "s" => 1,
+        "" | "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return None,
+    };
+    amount.checked_mul(unit_seconds)
+}
+
+/// Formats a unix timestamp so that Discord shows it in the reader's time zone
+pub fn format_timestamp(timestamp: u64) -> String {
+    format!("<t:{}:f>", timestamp)
+}
+
+/// Formats a number of seconds as a human readable duration, e.g. "4 minutes and 10 seconds"
+pub fn format_duration(seconds: u64) -> String {
+    let units = [(86400, "day"), (3600, "hour"), (60, "minute"), (1, "second")];
+    let mut parts = Vec::new();
+    let mut rest = seconds;
+    for (unit_seconds, name) in units.iter() {
+        let amount = rest / unit_seconds;
+        rest %= unit_seconds;
+        if amount > 0 {
+            let plural = if amount == 1 { "" } else { "s" };
+            parts.push(format!("{} {}{}", amount, name, plural));
+        }
+    }
+    match parts.len() {
+        0 => "0 seconds".to_string(),
+        1 => parts.remove(0),
+        _ => {
+            let last = parts.pop().unwrap();
+            format!("{} and {}", parts.join(", "), last)
+        }
+    }
+}
+
+/// Parses an ID given either as a mention matching `mention_regex` or as a raw number
+fn parse_id(arg: &str, mention_regex: &Regex) -> Option<u64> {
+    match mention_regex.captures(arg) {
+        Some(caps) => caps[1].parse().ok(),
+        None => arg.parse().ok(),
+    }
+}
+
+/// Returns the only item matching the predicate, or None if there are zero or several
+fn find_unique<T>(items: impl Iterator<Item = T>, predicate: impl Fn(&T) -> bool) -> Option<T> {
+    let mut matches = items.filter(|item| predicate(item));
+    match (matches.next(), matches.next()) {
+        (Some(item), None) => Some(item),
+        _ => None,
+    }
+}
+
+/// Fetches every member of the guild, following pagination
+pub async fn fetch_all_members(http: &HttpClient, guild_id: GuildId) -> Result<Vec<Member>> {
+    let mut members: Vec<Member> = Vec::new();
+    loop {
+        let mut request = http.guild_members(guild_id).limit(1000)?;
+        if let Some(last) = members.last() {
+            request = request.after(last.user.id);
+        }
+        let page = request.await?;
+        let page_len = page.len();
+        members.extend(page);
+        if page_len < 1000 {
+            return Ok(members);
+        }
+    }
+}
+
+/// Formats an emoji the way the reaction endpoints expect it
+pub fn reaction_emoji_string(emoji: &ReactionType) -> String {
+    match emoji {
+        ReactionType::Custom { id, name, .. } =>
+            format!("{}:{}", name.clone().unwrap_or_default(), id),
+        ReactionType::Unicode { name } => name.clone(),
+    }
+}
+
+/// Fetches every user who reacted to the message with the emoji, following pagination
+pub async fn fetch_reaction_users(
+    http: &HttpClient,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    emoji: &str,
+) -> Result<Vec<User>> {
+    let mut users: Vec<User> = Vec::new();
+    loop {
+        let mut request = http.reactions(channel_id, message_id, emoji).limit(100)?;
+        if let Some(last) = users.last() {
+            request = request.after(last.id);
+        }
+        let page = request.await?;
+        let page_len = page.len();
+        users.extend(page);
+        if page_len < 100 {
+            return Ok(users);
+        }
+    }
+}
+
+/**
+  Resolves a user from a command argument. Accepts mentions (`<@id>` and
+  `<@!id>`), raw IDs, `name#discriminator`, usernames and nicknames.
+
+  Names are compared case insensitively and must match exactly one member
+*/
+pub async fn parse_user(http: &HttpClient, guild_id: GuildId, arg: &str) -> Result<Option<UserId>> {
+    if let Some(id) = parse_id(arg, &USER_MENTION_REGEX) {
+        return Ok(Some(UserId(id)));
+    }
+    let name = arg.trim_start_matches('@').to_lowercase();
+    if name.is_empty() {
+        return Ok(None);
+    }
+    let members = fetch_all_members(http, guild_id).await?;
+    let found = match name.rfind('#') {
+        Some(split) if name[split + 1..].chars().all(|c| c.is_ascii_digit()) => {
+            let (username, discriminator) = (&name[..split], &name[split + 1..]);
+            find_unique(members.iter(), |member| {
+                member.user.name.to_lowercase() == username
+                    && member.user.discriminator == discriminator
+            })
+        }
+        _ => {
+            find_unique(members.iter(), |member| member.user.name.to_lowercase() == name)
+                .or_else(|| find_unique(members.iter(), |member| {
+                    member.nick.as_ref().map(|nick| nick.to_lowercase()) == Some(name.clone())
+                }))
+        }
+    };
+    Ok(found.map(|member| member.user.id))
+}
+
+/**
+  Resolves a channel from a command argument. Accepts mentions (`<#id>`),
+  raw IDs and channel names with or without a leading `#`
+*/
+pub async fn parse_channel(http: &HttpClient, guild_id: GuildId, arg: &str) -> Result<Option<ChannelId>> {
+    if let Some(id) = parse_id(arg, &CHANNEL_MENTION_REGEX) {
+        return Ok(Some(ChannelId(id)));
+    }
+    let name = arg.trim_start_matches('#').to_lowercase();
+    let channels = http.guild_channels(guild_id).await?;
+    Ok(find_unique(channels.iter(), |channel| channel.name().to_lowercase() == name)
+        .map(|channel| channel.id()))
+}
+
+/**
+  Resolves a category from a command argument like `parse_channel`, but
+  only matches categories, so a text channel of the same name is ignored
+*/
+pub async fn parse_category(http: &HttpClient, guild_id: GuildId, arg: &str) -> Result<Option<ChannelId>> {
+    let id = parse_id(arg, &CHANNEL_MENTION_REGEX).map(ChannelId);
+    let name = arg.to_lowercase();
+    let channels = http.guild_channels(guild_id).await?;
+    let categories = channels.iter().filter(|channel| match channel {
+        GuildChannel::Category(_) => true,
+        _ => false,
+    });
+    Ok(find_unique(categories, |channel| {
+        Some(channel.id()) == id || channel.name().to_lowercase() == name
+    }).map(|channel| channel.id()))
+}
+
+/**
+  Resolves a role from a command argument. Accepts mentions (`<@&id>`),
+  raw IDs and role names with or without a leading `@`
+*/
+pub async fn parse_role(http: &HttpClient, guild_id: GuildId, arg: &str) -> Result<Option<RoleId>> {
+    if let Some(id) = parse_id(arg, &ROLE_MENTION_REGEX) {
+        return Ok(Some(RoleId(id)));
+    }
+    let name = arg.trim_start_matches('@').to_lowercase();
+    let roles = guild_roles(http, guild_id).await?;
+    Ok(find_unique(roles.iter(), |role| role.name.to_lowercase() == name)
+        .map(|role| role.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_lines_are_joined() {
+        assert_eq!(split_message("aa\nb", 4), vec!["aa\nb"]);
+    }
+
+    #[test]
+    fn lines_are_split_when_over_the_limit() {
+        assert_eq!(split_message("aa\nbb", 4), vec!["aa", "bb"]);
+    }
+
+    #[test]
+    fn lines_of_exactly_the_limit_are_kept_whole() {
+        assert_eq!(split_message("abcd", 4), vec!["abcd"]);
+        assert_eq!(split_message("abcd\nefgh", 4), vec!["abcd", "efgh"]);
+    }
+
+    #[test]
+    fn long_lines_are_split_at_the_limit() {
+        assert_eq!(split_message("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(split_message("x\nabcdefgh", 4), vec!["x", "abcd", "efgh"]);
+    }
+
+    #[test]
+    fn long_lines_are_split_at_character_boundaries() {
+        assert_eq!(split_message("ééé", 3), vec!["é", "é", "é"]);
+    }
+
+    #[test]
+    fn empty_content_has_no_chunks() {
+        assert!(split_message("", 10).is_empty());
+    }
+
+    #[test]
+    fn durations_are_parsed_with_units() {
+        assert_eq!(parse_duration("45s"), Some(45));
+        assert_eq!(parse_duration("90m"), Some(90 * 60));
+        assert_eq!(parse_duration("12h"), Some(12 * 3600));
+        assert_eq!(parse_duration("3d"), Some(3 * 86400));
+        assert_eq!(parse_duration("2w"), Some(14 * 86400));
+    }
+
+    #[test]
+    fn durations_without_unit_are_minutes() {
+        assert_eq!(parse_duration("30"), Some(30 * 60));
+    }
+
+    #[test]
+    fn invalid_durations_are_rejected() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("5x"), None);
+        assert_eq!(parse_duration("-5m"), None);
+        assert_eq!(parse_duration("1.5h"), None);
+        assert_eq!(parse_duration("18446744073709551615d"), None);
+    }
+
+    #[test]
+    fn durations_are_formatted() {
+        assert_eq!(format_duration(0), "0 seconds");
+        assert_eq!(format_duration(250), "4 minutes and 10 seconds");
+        assert_eq!(format_duration(90061), "1 day, 1 hour, 1 minute and 1 second");
+    }
+
+    #[test]
+    fn ids_are_parsed_from_mentions_and_numbers() {
+        assert_eq!(parse_id("<@123>", &USER_MENTION_REGEX), Some(123));
+        assert_eq!(parse_id("<@!123>", &USER_MENTION_REGEX), Some(123));
+        assert_eq!(parse_id("123", &USER_MENTION_REGEX), Some(123));
+        assert_eq!(parse_id("<#123>", &USER_MENTION_REGEX), None);
+        assert_eq!(parse_id("<#123>", &CHANNEL_MENTION_REGEX), Some(123));
+        assert_eq!(parse_id("someone", &USER_MENTION_REGEX), None);
+    }
+
+    #[test]
+    fn only_unique_matches_are_found() {
+        assert_eq!(find_unique(vec![1, 2, 3].into_iter(), |n| n % 2 == 0), Some(2));
+        assert_eq!(find_unique(vec![1, 2, 3].into_iter(), |n| *n > 1), None);
+        assert_eq!(find_unique(vec![1, 2, 3].into_iter(), |n| *n > 5), None);
+    }
+}
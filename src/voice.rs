@@ -0,0 +1,236 @@
+use serde_derive::{Serialize, Deserialize};
+use twilight::{
+    http::{
+        Client as HttpClient,
+        error::Error as DiscordError,
+        request::Request,
+        routing::Route,
+    },
+    model::{
+        channel::Channel,
+        id::{ChannelId, GuildId},
+        user::User,
+    },
+};
+
+use crate::permissions::{has_permission, permission_denied};
+use crate::state::PersistentState;
+use crate::utils::{Result, send_message};
+
+/// The largest user limit Discord allows on a voice channel. 0 means no limit
+const MAX_USER_LIMIT: u64 = 99;
+/// Bitrate bounds in bits per second. The upper bound requires a boosted server
+const MIN_BITRATE: u64 = 8000;
+const MAX_BITRATE: u64 = 384000;
+/// The bitrate Discord gives new voice channels
+const DEFAULT_BITRATE: u64 = 64000;
+
+/// The longest voice region ID accepted by `!setvoiceconfig`
+const MAX_REGION_LENGTH: usize = 32;
+
+/**
+  Settings applied to the voice channels of teams. Unset values use
+  Discord's defaults, which for the region means picking one automatically
+*/
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct VoiceConfig {
+    pub user_limit: Option<u64>,
+    pub bitrate: Option<u64>,
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+/**
+  Parses a voice region ID like `rotterdam` or `us-east`, where `default`
+  unsets it. Discord rejects IDs it doesn't know when the region is applied
+*/
+fn parse_region(arg: &str) -> Option<Option<String>> {
+    if arg == "default" {
+        Some(None)
+    }
+    else if !arg.is_empty()
+        && arg.len() <= MAX_REGION_LENGTH
+        && arg.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        Some(Some(arg.to_string()))
+    }
+    else {
+        None
+    }
+}
+
+/**
+  Sets the region of a voice channel, where `None` lets Discord pick one
+  automatically. The channel builders have no region option, so the
+  request is made by hand
+*/
+pub async fn set_voice_region(
+    http: &HttpClient,
+    channel: ChannelId,
+    region: Option<&str>
+) -> std::result::Result<(), DiscordError> {
+    let body = serde_json::json!({ "rtc_region": region }).to_string().into_bytes();
+    let request = Request::from((body, Route::UpdateChannel { channel_id: channel.0 }));
+    http.request::<Channel>(request).await?;
+    Ok(())
+}
+
+/// Parses a setting value, where `default` unsets it
+fn parse_setting(arg: &str, min: u64, max: u64) -> Option<Option<u64>> {
+    if arg == "default" {
+        Some(None)
+    }
+    else {
+        arg.parse::<u64>().ok()
+            .filter(|value| *value >= min && *value <= max)
+            .map(Some)
+    }
+}
+
+/// A team voice channel setting changed by `!setvoiceconfig`
+#[derive(Clone)]
+enum VoiceSetting {
+    UserLimit(Option<u64>),
+    Bitrate(Option<u64>),
+    Region(Option<String>),
+}
+
+fn format_setting(value: Option<u64>, unit: &str) -> String {
+    match value {
+        Some(value) => format!("**{}**{}", value, unit),
+        None => "Discord's default".to_string(),
+    }
+}
+
+fn format_region(region: &Option<String>) -> String {
+    match region {
+        Some(region) => format!("**{}**", region),
+        None => "automatic".to_string(),
+    }
+}
+
+pub async fn handle_set_voice_config<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    guild: GuildId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    if !has_permission(&http, guild, author.id, "setvoiceconfig").await? {
+        permission_denied(&http, original_channel, author.id, "setvoiceconfig", "configure team voice channels").await?;
+        return Ok(());
+    }
+
+    let usage = format!(
+        "Proper usage: `!setvoiceconfig limit <0-{}|default>` or \
+        `!setvoiceconfig bitrate <{}-{}|default>` or \
+        `!setvoiceconfig region <region id|default>`", MAX_USER_LIMIT, MIN_BITRATE, MAX_BITRATE
+    );
+    let changed = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let setting = match (rest_command.get(0), rest_command.get(1)) {
+            (Some(&"limit"), Some(value)) => parse_setting(value, 0, MAX_USER_LIMIT).map(VoiceSetting::UserLimit),
+            (Some(&"bitrate"), Some(value)) => parse_setting(value, MIN_BITRATE, MAX_BITRATE).map(VoiceSetting::Bitrate),
+            (Some(&"region"), Some(value)) => parse_region(value).map(VoiceSetting::Region),
+            _ => None,
+        };
+        match setting {
+            Some(setting) => {
+                match &setting {
+                    VoiceSetting::UserLimit(limit) => ps.voice_config.user_limit = *limit,
+                    VoiceSetting::Bitrate(bitrate) => ps.voice_config.bitrate = *bitrate,
+                    VoiceSetting::Region(region) => ps.voice_config.region = region.clone(),
+                }
+                ps.save()?;
+                Some((setting, ps.voice_config.clone(), ps.channel_creators.values().cloned().collect::<Vec<_>>()))
+            }
+            None => None,
+        }
+    };
+
+    match changed {
+        Some((setting, config, teams)) => {
+            // Apply the changed setting to the teams that already have channels,
+            // setting Discord's default explicitly when the setting was unset
+            let mut failed = 0;
+            for team in &teams {
+                let result = match &setting {
+                    VoiceSetting::UserLimit(limit) => http.update_channel(team.voice_id)
+                        .user_limit(limit.unwrap_or(0))
+                        .await
+                        .map(|_| ()),
+                    VoiceSetting::Bitrate(bitrate) => http.update_channel(team.voice_id)
+                        .bitrate(bitrate.unwrap_or(DEFAULT_BITRATE))
+                        .await
+                        .map(|_| ()),
+                    VoiceSetting::Region(region) => set_voice_region(&http, team.voice_id, region.as_deref()).await,
+                };
+                if let Err(e) = result {
+                    println!("Failed to update voice channel of {}: {:?}", team.game_name, e);
+                    failed += 1;
+                }
+            }
+            let failures = if failed > 0 {
+                format!(" Updating {} of them failed.", failed)
+            }
+            else {
+                String::new()
+            };
+            send_message(&http, original_channel, author.id,
+                format!(
+                    "Team voice channels now use the user limit {}, the bitrate {} and the region {}. \
+                    Applied to {} existing teams.{}",
+                    format_setting(config.user_limit, ""), format_setting(config.bitrate, " bps"),
+                    format_region(&config.region), teams.len(), failures
+                )
+            ).await?;
+        }
+        None => {
+            send_message(&http, original_channel, author.id, usage).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_voice_limit<'a>(
+    rest_command: &[&'a str],
+    original_channel: ChannelId,
+    author: &User,
+    http: HttpClient
+) -> Result<()> {
+    let (team, max_limit) = {
+        let mut ps = PersistentState::instance().lock().unwrap();
+        let max_limit = ps.voice_config.user_limit.filter(|limit| *limit > 0).unwrap_or(MAX_USER_LIMIT);
+        (ps.get_channel_info(author.id).cloned(), max_limit)
+    };
+
+    match (team, rest_command.get(0).and_then(|arg| arg.parse::<u64>().ok())) {
+        (None, _) => {
+            send_message(&http, original_channel, author.id,
+                "You have not created a channel yet.\n\
+                Try using `!createchannels <game name>` first."
+            ).await?;
+        }
+        (Some(team), Some(limit)) if limit > 0 && limit <= max_limit => {
+            match http.update_channel(team.voice_id).user_limit(limit).await {
+                Ok(_) => {
+                    send_message(&http, original_channel, author.id,
+                        format!("The voice channel for **{}** now fits **{}** users.", team.game_name, limit)
+                    ).await?;
+                }
+                Err(e) => {
+                    send_message(&http, original_channel, author.id,
+                        format!("Could not update the voice channel for **{}**.", team.game_name)
+                    ).await?;
+                    println!("Failed to set voice limit for {}: {:?}", team.game_name, e);
+                }
+            }
+        }
+        _ => {
+            send_message(&http, original_channel, author.id,
+                format!("Proper usage: `!voicelimit <1-{}>`", max_limit)
+            ).await?;
+        }
+    }
+    Ok(())
+}